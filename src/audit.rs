@@ -0,0 +1,357 @@
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::journal::Mutation;
+use crate::schema::Record;
+use crate::storage::{open_for_append, sibling_path};
+use crate::types::{BazaDate, Client, Comanda};
+
+/// A mutation made through `BazaDate`, as kept in the audit log.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AuditEntry {
+    /// When the mutation was made, in UTC, like `2026-10-18T09:30:00Z`.
+    pub time: String,
+    /// The operating system user who made it.
+    pub user: String,
+    /// The operation, named as in the journal, such as `update`.
+    pub operation: String,
+    pub table: String,
+    /// Id of the record the mutation applies to.
+    pub id: i32,
+    /// The fields of the record before the mutation, unless it is new.
+    pub before: Option<BTreeMap<String, String>>,
+    /// The fields of the record after the mutation, unless it is deleted.
+    pub after: Option<BTreeMap<String, String>>,
+}
+
+impl AuditEntry {
+    /// Describes a mutation about to be applied to `bd`.
+    pub fn new(bd: &BazaDate, mutation: &Mutation) -> AuditEntry {
+        let (table, id, before, after) = match mutation {
+            Mutation::Add(client) => (
+                Client::SCHEMA.name,
+                client.nr_ordine,
+                None,
+                Some(fields(client)),
+            ),
+            Mutation::Update(client) => (
+                Client::SCHEMA.name,
+                client.nr_ordine,
+                bd.get_record(client.nr_ordine).map(fields),
+                Some(fields(client)),
+            ),
+            Mutation::Delete(nr_ordine) => (
+                Client::SCHEMA.name,
+                *nr_ordine,
+                bd.get_record(*nr_ordine).map(fields),
+                None,
+            ),
+            Mutation::AddOrder(order) => (
+                Comanda::SCHEMA.name,
+                order.nr_comanda,
+                None,
+                Some(fields(order)),
+            ),
+            Mutation::DeleteOrder(nr_comanda) => (
+                Comanda::SCHEMA.name,
+                *nr_comanda,
+                bd.get_order(*nr_comanda).map(fields),
+                None,
+            ),
+        };
+        AuditEntry {
+            time: timestamp(SystemTime::now()),
+            user: os_user(),
+            operation: mutation.operation().to_string(),
+            table: table.to_string(),
+            id,
+            before,
+            after,
+        }
+    }
+
+    /// The fields whose value differs between `before` and `after`, with
+    /// their old and new values, empty when missing.
+    pub fn changes(&self) -> Vec<(&str, &str, &str)> {
+        fn value<'a>(fields: &'a Option<BTreeMap<String, String>>, name: &str) -> &'a str {
+            fields
+                .as_ref()
+                .and_then(|fields| fields.get(name))
+                .map_or("", String::as_str)
+        }
+        let mut names: Vec<&str> = self
+            .before
+            .iter()
+            .chain(&self.after)
+            .flat_map(|fields| fields.keys().map(String::as_str))
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+            .into_iter()
+            .filter_map(|name| {
+                let (old, new) = (value(&self.before, name), value(&self.after, name));
+                (old != new).then_some((name, old, new))
+            })
+            .collect()
+    }
+
+    /// The changed fields, written like `nume: Pop -> Popescu`.
+    pub fn summary(&self) -> String {
+        let changes: Vec<String> = self
+            .changes()
+            .iter()
+            .map(|(name, old, new)| match (old.is_empty(), new.is_empty()) {
+                (true, _) => format!("{}: {}", name, new),
+                (false, true) => format!("{}: {} -> ", name, old),
+                (false, false) => format!("{}: {} -> {}", name, old, new),
+            })
+            .collect();
+        changes.join(", ")
+    }
+
+    /// Whether the time, user, operation, table, id or changes of the
+    /// entry contain `text`, ignoring case.
+    pub fn matches(&self, text: &str) -> bool {
+        let text = text.to_lowercase();
+        [
+            self.time.as_str(),
+            &self.user,
+            &self.operation,
+            &self.table,
+            &self.id.to_string(),
+            &self.summary(),
+        ]
+        .iter()
+        .any(|value| value.to_lowercase().contains(&text))
+    }
+
+    /// Whether the entry was made on a day from `from` to `to`, both
+    /// written like `2026-10-18` and both included.
+    pub fn within(&self, from: Option<&str>, to: Option<&str>) -> bool {
+        let day = self.time.get(..10).unwrap_or(&self.time);
+        from.is_none_or(|from| day >= from) && to.is_none_or(|to| day <= to)
+    }
+}
+
+/// Errors raised while reading the audit log.
+#[derive(Debug, Error)]
+pub enum AuditError {
+    #[error("audit log line {line}: {source}")]
+    Entry {
+        line: usize,
+        source: serde_json::Error,
+    },
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+/// Append-only log of every mutation made to a database, kept next to it
+/// as one JSON object per line.
+///
+/// Unlike the journal it is never emptied: entries are written before the
+/// mutation is applied, and only ever added.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuditLog {
+    path: PathBuf,
+}
+
+impl AuditLog {
+    /// The audit log kept next to the database at `db_path`.
+    pub fn for_database(db_path: &Path) -> AuditLog {
+        AuditLog {
+            path: sibling_path(db_path, ".audit"),
+        }
+    }
+
+    /// Appends an entry and waits for it to reach the disk, after cutting
+    /// off a last line an earlier append left unfinished.
+    pub fn append(&self, entry: &AuditEntry) -> io::Result<()> {
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+        let mut file = open_for_append(&self.path)?;
+        file.write_all(line.as_bytes())?;
+        file.sync_data()
+    }
+
+    /// Reads every entry, oldest first.
+    ///
+    /// A last line with no newline was cut short while being appended and
+    /// is ignored, like in the journal.
+    pub fn entries(&self) -> Result<Vec<AuditEntry>, AuditError> {
+        let contents = match fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(error) => return Err(error.into()),
+        };
+        let complete = match contents.rfind('\n') {
+            Some(end) => &contents[..=end],
+            None => "",
+        };
+        complete
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(index, line)| {
+                serde_json::from_str(line).map_err(|source| AuditError::Entry {
+                    line: index + 1,
+                    source,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Writes the entries as newline-delimited JSON, as they are in the log.
+pub fn write_ndjson(mut writer: impl Write, entries: &[AuditEntry]) -> io::Result<()> {
+    for entry in entries {
+        serde_json::to_writer(&mut writer, entry)?;
+        writer.write_all(b"\n")?;
+    }
+    writer.flush()
+}
+
+/// Writes the entries as CSV, with the fields before and after each
+/// mutation as JSON objects.
+pub fn write_csv(writer: impl Write, entries: &[AuditEntry]) -> Result<(), csv::Error> {
+    let mut writer = csv::Writer::from_writer(writer);
+    writer.write_record([
+        "time",
+        "user",
+        "operation",
+        "table",
+        "id",
+        "before",
+        "after",
+    ])?;
+    for entry in entries {
+        let json = |fields: &Option<BTreeMap<String, String>>| match fields {
+            Some(fields) => serde_json::to_string(fields).unwrap_or_default(),
+            None => String::new(),
+        };
+        writer.write_record([
+            entry.time.as_str(),
+            &entry.user,
+            &entry.operation,
+            &entry.table,
+            &entry.id.to_string(),
+            &json(&entry.before),
+            &json(&entry.after),
+        ])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// The fields of a record, by column name.
+fn fields<R: Record>(record: &R) -> BTreeMap<String, String> {
+    R::SCHEMA
+        .columns
+        .iter()
+        .map(|column| (column.name.to_string(), (column.get)(record)))
+        .collect()
+}
+
+/// The name of the user running the app, as given by the environment.
+fn os_user() -> String {
+    ["USER", "USERNAME", "LOGNAME"]
+        .iter()
+        .find_map(|var| env::var(var).ok().filter(|user| !user.is_empty()))
+        .unwrap_or_else(|| String::from("unknown"))
+}
+
+/// Formats a time as an RFC 3339 timestamp in UTC, to the second.
+fn timestamp(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs() as i64);
+    let (days, rest) = (seconds.div_euclid(86_400), seconds.rem_euclid(86_400));
+
+    // Converts days since 1970-01-01 to a civil date, counting in 400-year
+    // eras that start on March 1st so leap days fall at the end of a year.
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rest / 3_600,
+        rest % 3_600 / 60,
+        rest % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TestDir;
+    use std::time::Duration;
+
+    #[test]
+    fn timestamps_are_utc_dates() {
+        let at = |seconds| timestamp(UNIX_EPOCH + Duration::from_secs(seconds));
+        assert_eq!(at(0), "1970-01-01T00:00:00Z");
+        assert_eq!(at(951_782_400), "2000-02-29T00:00:00Z");
+        assert_eq!(at(1_792_312_245), "2026-10-18T08:30:45Z");
+    }
+
+    #[test]
+    fn entries_keep_the_fields_before_and_after() {
+        let mut bd = BazaDate::new();
+        let nr_ordine = bd.add_record(Client::new()).unwrap();
+        let update = Mutation::Update(Client {
+            nr_ordine,
+            nume: String::from("Pop"),
+            ..Client::new()
+        });
+
+        let entry = AuditEntry::new(&bd, &update);
+        assert_eq!((entry.operation.as_str(), entry.id), ("update", nr_ordine));
+        assert_eq!(entry.changes(), [("nume", "", "Pop")]);
+        assert_eq!(entry.summary(), "nume: Pop");
+        assert!(entry.matches("UPDATE") && !entry.matches("comenzi"));
+        assert!(entry.within(Some(&entry.time[..10]), None));
+        assert!(!entry.within(None, Some("2000-01-01")));
+    }
+
+    #[test]
+    fn a_torn_last_line_is_ignored_and_cut_off_by_the_next_append() {
+        let dir = TestDir::new("audit");
+        let log = AuditLog::for_database(&dir.database());
+
+        let bd = BazaDate::new();
+        let entry = AuditEntry::new(&bd, &Mutation::Add(Client::new()));
+        log.append(&entry).unwrap();
+        let mut file = fs::OpenOptions::new().append(true).open(&log.path).unwrap();
+        file.write_all(b"{\"time\":\"2026-").unwrap();
+
+        assert_eq!(log.entries().unwrap(), std::slice::from_ref(&entry));
+
+        let next = AuditEntry::new(&bd, &Mutation::Delete(0));
+        log.append(&next).unwrap();
+        assert_eq!(log.entries().unwrap(), [entry, next]);
+    }
+}
@@ -0,0 +1,808 @@
+use std::{
+    convert::Infallible,
+    env,
+    fs::{self, File},
+    io::{self, BufReader, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use thiserror::Error;
+
+use crate::audit::{self, AuditEntry, AuditError, AuditLog};
+use crate::csv_io::{read_csv, write_csv, ColumnMapping, CsvError};
+use crate::functions::query_records;
+use crate::journal::Journal;
+use crate::json_io::{read_json, read_ndjson, write_json, write_ndjson, JsonDatabase, JsonError};
+use crate::query::{ParseError, Query, SearchMode};
+use crate::schema::{Record, SortKey, SortMode};
+use crate::storage::{escape_field, open_storage, DatabaseError, SaveOptions, Storage};
+use crate::types::{BazaDate, Client, Comanda, ImportReport, IntegrityError, OnDelete, SortOrd};
+use crate::vcard::{read_vcards, write_vcards, VcardVersion};
+
+/// Manage the client database from the terminal.
+///
+/// Without a subcommand the interactive interface is started.
+#[derive(Parser)]
+#[command(name = "db_cli-rs", version)]
+pub struct Cli {
+    /// Database to open, optionally named; repeat to open several.
+    /// Subcommands work on the first one. Defaults to `$DB_CLI_DB`,
+    /// a list of databases separated like `$PATH`
+    #[arg(long = "db", value_name = "[NAME=]PATH", global = true)]
+    pub databases: Vec<DatabaseSpec>,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Environment variable listing the databases to open.
+const DATABASES_VAR: &str = "DB_CLI_DB";
+/// Database used when none is given.
+const DEFAULT_DATABASE: &str = "./baza_de_date.io";
+
+impl Cli {
+    /// The databases to open, from the command line, the environment
+    /// or the default path, in that order of preference.
+    pub fn database_specs(&self) -> Vec<DatabaseSpec> {
+        if !self.databases.is_empty() {
+            return self.databases.clone();
+        }
+
+        let from_env: Vec<DatabaseSpec> = env::var_os(DATABASES_VAR)
+            .map(|value| {
+                env::split_paths(&value)
+                    .filter(|path| !path.as_os_str().is_empty())
+                    .filter_map(|path| path.to_str().and_then(|spec| spec.parse().ok()))
+                    .collect()
+            })
+            .unwrap_or_default();
+        if !from_env.is_empty() {
+            return from_env;
+        }
+
+        vec![DatabaseSpec::from_path(PathBuf::from(DEFAULT_DATABASE))]
+    }
+}
+
+/// A database to open, written as `[NAME=]PATH`.
+///
+/// Without a name, the file name of the path is used.
+#[derive(Clone, Debug)]
+pub struct DatabaseSpec {
+    pub name: String,
+    pub path: PathBuf,
+}
+
+impl DatabaseSpec {
+    fn from_path(path: PathBuf) -> DatabaseSpec {
+        let name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_else(|| path.display().to_string());
+        DatabaseSpec { name, path }
+    }
+}
+
+impl FromStr for DatabaseSpec {
+    type Err = Infallible;
+
+    fn from_str(spec: &str) -> Result<DatabaseSpec, Infallible> {
+        Ok(match spec.split_once('=') {
+            Some((name, path)) if !name.is_empty() => DatabaseSpec {
+                name: name.to_string(),
+                path: PathBuf::from(path),
+            },
+            _ => DatabaseSpec::from_path(PathBuf::from(spec)),
+        })
+    }
+}
+
+/// The non-interactive subcommands.
+#[derive(Subcommand)]
+pub enum Command {
+    /// Print every record, one per line
+    List {
+        /// Fields the records are sorted by, like `last,first:desc,id`
+        #[arg(long, value_parser = parse_sort, value_delimiter = ',', default_value = "id")]
+        sort: Vec<SortKey>,
+        /// Sort every field the other way
+        #[arg(long)]
+        desc: bool,
+    },
+    /// Print the record with the given id
+    Get { id: i32 },
+    /// Add a record and print its id
+    Add {
+        #[arg(long)]
+        first: String,
+        #[arg(long)]
+        last: String,
+        #[arg(long, default_value = "")]
+        phone: String,
+        #[arg(long, default_value = "")]
+        address: String,
+    },
+    /// Change some of the fields of a record
+    Update {
+        id: i32,
+        #[arg(long)]
+        first: Option<String>,
+        #[arg(long)]
+        last: Option<String>,
+        #[arg(long)]
+        phone: Option<String>,
+        #[arg(long)]
+        address: Option<String>,
+    },
+    /// Delete the record with the given id
+    ///
+    /// A client who still has orders is only deleted with `--cascade`.
+    Delete {
+        id: i32,
+        /// Delete the orders of the client too
+        #[arg(long)]
+        cascade: bool,
+    },
+    /// Print the orders, one per line, after their id and client id
+    Orders {
+        /// Only print the orders of this client
+        #[arg(long)]
+        client: Option<i32>,
+    },
+    /// Add an order for an existing client and print its id
+    AddOrder {
+        #[arg(long)]
+        client: i32,
+        #[arg(long)]
+        product: String,
+        #[arg(long, default_value_t = 1)]
+        quantity: i32,
+        #[arg(long, default_value = "")]
+        date: String,
+    },
+    /// Delete the order with the given id
+    DeleteOrder { id: i32 },
+    /// Print the records matching a query, like `last:Pop AND NOT phone:^07`
+    Search {
+        /// Field searched by the terms of the query that name none, instead
+        /// of every field
+        #[arg(long, value_parser = parse_column)]
+        field: Option<usize>,
+        /// Read the pattern as a fuzzy one, closest matches first
+        #[arg(long)]
+        fuzzy: bool,
+        /// Read the pattern as a regular expression
+        #[arg(long, conflicts_with = "fuzzy")]
+        regex: bool,
+        pattern: String,
+    },
+    /// Add the records of a CSV file with a header row
+    ///
+    /// Rows that fail validation are reported and skipped; the exit code
+    /// is 4 if there were any.
+    ImportCsv {
+        file: PathBuf,
+        /// Only report what would be imported
+        #[arg(long)]
+        dry_run: bool,
+        /// Import a column into a field, one of nume, prenume, nr_telefon, adresa
+        #[arg(long = "map", value_name = "HEADER=FIELD")]
+        mappings: Vec<ColumnMapping>,
+    },
+    /// Write the records to a CSV file, or to stdout if no file is given
+    ///
+    /// Without a pattern the whole database is written.
+    ExportCsv {
+        file: Option<PathBuf>,
+        #[command(flatten)]
+        view: ViewArgs,
+    },
+    /// Add the records of a JSON or NDJSON export
+    ///
+    /// The file is checked against the schema first and nothing is
+    /// imported if any record is invalid.
+    ImportJson {
+        file: PathBuf,
+        /// Read newline-delimited JSON; implied by a `.ndjson` or `.jsonl` file
+        #[arg(long)]
+        ndjson: bool,
+        /// Replace every record of the database, keeping the ids of the file
+        #[arg(long)]
+        replace: bool,
+        /// With `--replace`, delete the orders of the replaced clients too
+        #[arg(long, requires = "replace")]
+        cascade: bool,
+        /// Only check the file
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Write the records as JSON, to a file or to stdout
+    ///
+    /// Without a pattern the whole database is written.
+    ExportJson {
+        file: Option<PathBuf>,
+        /// Write newline-delimited JSON; implied by a `.ndjson` or `.jsonl` file
+        #[arg(long)]
+        ndjson: bool,
+        #[command(flatten)]
+        view: ViewArgs,
+    },
+    /// Copy the database into another file, converting between backends
+    ///
+    /// The backend of each side is chosen by its extension: `.sqlite`,
+    /// `.sqlite3` and `.db` files are SQLite databases.
+    Migrate {
+        to: PathBuf,
+        /// Replace the records the destination already holds
+        #[arg(long)]
+        force: bool,
+    },
+    /// Add the contacts of a vCard file
+    ///
+    /// Cards without a name are reported and skipped; the exit code is 4
+    /// if there were any.
+    ImportVcard {
+        file: PathBuf,
+        /// Only report what would be imported
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Write the records as vCards, to a file or to stdout
+    ///
+    /// Without ids or a pattern the whole database is written.
+    ExportVcard {
+        file: Option<PathBuf>,
+        /// Only write the record with this id; repeat to select several
+        #[arg(long = "id", value_name = "ID")]
+        ids: Vec<i32>,
+        /// vCard version written
+        #[arg(long, value_enum, default_value_t = CardVersion::V3)]
+        card_version: CardVersion,
+        #[command(flatten)]
+        view: ViewArgs,
+    },
+    /// Write the audit log of the database, to a file or to stdout
+    ///
+    /// Entries are written as NDJSON, one per line, oldest first.
+    ExportAudit {
+        file: Option<PathBuf>,
+        /// Only write the entries made on this day or later, like 2026-10-01
+        #[arg(long, value_parser = parse_day)]
+        from: Option<String>,
+        /// Only write the entries made on this day or earlier
+        #[arg(long, value_parser = parse_day)]
+        to: Option<String>,
+        /// Write CSV, with the fields before and after as JSON objects
+        #[arg(long)]
+        csv: bool,
+    },
+}
+
+/// The records an export writes, and their order.
+#[derive(Args)]
+pub struct ViewArgs {
+    /// Only write the records matching this query
+    #[arg(long)]
+    pattern: Option<String>,
+    /// Field searched by the terms of the query that name none, instead of
+    /// every field
+    #[arg(long, value_parser = parse_column)]
+    field: Option<usize>,
+    /// Fields the records are sorted by, like `last,first:desc,id`
+    #[arg(long, value_parser = parse_sort, value_delimiter = ',', default_value = "id")]
+    sort: Vec<SortKey>,
+    /// Sort every field the other way
+    #[arg(long)]
+    desc: bool,
+}
+
+impl ViewArgs {
+    /// Whether the view holds every record by increasing id.
+    fn is_everything_by_id(&self) -> bool {
+        self.pattern.is_none() && !self.desc && is_by_id(&self.sort)
+    }
+
+    /// The matching records, in the requested order.
+    fn query(&self, bd: &BazaDate) -> Result<Vec<Client>, ParseError> {
+        let pattern = self.pattern.as_deref().unwrap_or_default();
+        let query = Query::parse(pattern, self.field)?;
+        Ok(query_records(
+            &bd.clienti,
+            &query,
+            &sort_keys(&self.sort, self.desc),
+        ))
+    }
+}
+
+/// Whether records sorted by `sort` come by increasing id.
+fn is_by_id(sort: &[SortKey]) -> bool {
+    sort.first() == Some(&SortKey::new(SortMode::Id, SortOrd::Incr))
+}
+
+/// Whether the command reads every client by increasing id, and nothing else.
+fn reads_in_id_order(command: &Command) -> bool {
+    match command {
+        Command::List { sort, desc } => !desc && is_by_id(sort),
+        Command::ExportCsv { view, .. } => view.is_everything_by_id(),
+        Command::ExportVcard { ids, view, .. } => ids.is_empty() && view.is_everything_by_id(),
+        _ => false,
+    }
+}
+
+/// Parses a column of the client schema, by name or alias.
+fn parse_column(name: &str) -> Result<usize, String> {
+    Client::SCHEMA.column(name).ok_or_else(|| {
+        format!(
+            "unknown field `{}`, expected one of {}",
+            name,
+            Client::SCHEMA.column_names()
+        )
+    })
+}
+
+/// Parses a key the records are sorted by: `id` or a column, followed by
+/// `:desc` to sort it in decreasing order, or `:asc`.
+fn parse_sort(key: &str) -> Result<SortKey, String> {
+    let (name, order) = match key.split_once(':') {
+        None => (key, SortOrd::Incr),
+        Some((name, "asc")) => (name, SortOrd::Incr),
+        Some((name, "desc")) => (name, SortOrd::Decr),
+        Some((_, order)) => {
+            return Err(format!("unknown order `{}`, expected asc or desc", order));
+        }
+    };
+    let mode = match name {
+        "id" | "nr_ordine" => SortMode::Id,
+        name => SortMode::Column(parse_column(name)?),
+    };
+    Ok(SortKey::new(mode, order))
+}
+
+/// The sort keys asked for, every one reversed by `--desc`.
+fn sort_keys(sort: &[SortKey], desc: bool) -> Vec<SortKey> {
+    sort.iter()
+        .map(|key| if desc { key.reversed() } else { *key })
+        .collect()
+}
+
+/// Parses a day written like `2026-10-18`.
+fn parse_day(day: &str) -> Result<String, String> {
+    let valid = day.len() == 10
+        && day.char_indices().all(|(index, c)| match index {
+            4 | 7 => c == '-',
+            _ => c.is_ascii_digit(),
+        });
+    match valid {
+        true => Ok(day.to_string()),
+        false => Err(format!("expected a day like 2026-10-18, found `{}`", day)),
+    }
+}
+
+/// A vCard version, as named on the command line.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum CardVersion {
+    #[value(name = "3.0")]
+    V3,
+    #[value(name = "4.0")]
+    V4,
+}
+
+impl From<CardVersion> for VcardVersion {
+    fn from(version: CardVersion) -> VcardVersion {
+        match version {
+            CardVersion::V3 => VcardVersion::V3,
+            CardVersion::V4 => VcardVersion::V4,
+        }
+    }
+}
+
+/// Errors ending a subcommand, each with its own exit code.
+#[derive(Debug, Error)]
+pub enum CliError {
+    #[error("no record with id {0}")]
+    NotFound(i32),
+    #[error("{0}")]
+    Invalid(String),
+    #[error("invalid query at column {}: {0}", .0.span.start + 1)]
+    Query(#[from] ParseError),
+    #[error(transparent)]
+    Integrity(#[from] IntegrityError),
+    #[error(transparent)]
+    Database(#[from] DatabaseError),
+    #[error(transparent)]
+    Csv(#[from] CsvError),
+    #[error(transparent)]
+    Json(#[from] JsonError),
+    #[error(transparent)]
+    Audit(#[from] AuditError),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+impl CliError {
+    /// The exit code reported to the shell.
+    ///
+    /// `2` is left to usage errors, which are reported by the argument parser.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            CliError::NotFound(_) => 3,
+            CliError::Invalid(_) | CliError::Query(_) => 4,
+            CliError::Integrity(IntegrityError::MissingClient(_)) => 3,
+            CliError::Integrity(IntegrityError::HasOrders { .. }) => 5,
+            CliError::Integrity(IntegrityError::Io(_)) => 1,
+            CliError::Json(JsonError::Io(_)) => 1,
+            CliError::Json(_) => 4,
+            CliError::Database(_) | CliError::Csv(_) | CliError::Audit(_) | CliError::Io(_) => 1,
+        }
+    }
+}
+
+/// Records read at a time when listing.
+const PAGE_SIZE: usize = 1000;
+
+/// Runs a subcommand against the database stored at `db_path`.
+pub fn run(command: Command, db_path: &Path, options: &SaveOptions) -> Result<(), CliError> {
+    let storage = open_storage(db_path, *options)?;
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+
+    // Reading every record in storage order needs no copy of the database
+    // in memory, as long as the journal holds nothing the storage is missing.
+    if reads_in_id_order(&command) && Journal::for_database(db_path).entries()?.is_empty() {
+        let mut error = None;
+        let clients = storage
+            .pages(PAGE_SIZE)
+            .scan(&mut error, |error, page| match page {
+                Ok(page) => Some(page),
+                Err(page_error) => {
+                    **error = Some(page_error);
+                    None
+                }
+            })
+            .flatten();
+        match command {
+            Command::ExportCsv {
+                file: Some(file), ..
+            } => write_csv(File::create(file)?, clients).map_err(CsvError::from)?,
+            Command::ExportCsv { file: None, .. } => {
+                write_csv(&mut out, clients).map_err(CsvError::from)?
+            }
+            Command::ExportVcard {
+                file: Some(file),
+                card_version,
+                ..
+            } => write_vcards(File::create(file)?, clients, card_version.into())?,
+            Command::ExportVcard {
+                file: None,
+                card_version,
+                ..
+            } => write_vcards(&mut out, clients, card_version.into())?,
+            _ => {
+                for client in clients {
+                    print_records(&mut out, std::slice::from_ref(&client))?;
+                }
+            }
+        }
+        return match error {
+            Some(error) => Err(error.into()),
+            None => Ok(()),
+        };
+    }
+
+    let mut bd = storage.load()?;
+
+    match command {
+        Command::List { sort, desc } => {
+            let clients = query_records(&bd.clienti, &Query::default(), &sort_keys(&sort, desc));
+            print_records(&mut out, &clients)?;
+        }
+        Command::Get { id } => {
+            let client = bd.get_record(id).ok_or(CliError::NotFound(id))?;
+            print_records(&mut out, std::slice::from_ref(client))?;
+        }
+        Command::Add {
+            first,
+            last,
+            phone,
+            address,
+        } => {
+            let client = Client {
+                nr_ordine: 0,
+                nume: last,
+                prenume: first,
+                nr_telefon: phone,
+                adresa: address,
+            };
+            let client = Client::SCHEMA.check(&client).map_err(CliError::Invalid)?;
+            let id = bd.add_record(client)?;
+            commit(&bd, &*storage)?;
+            writeln!(out, "{}", id)?;
+        }
+        Command::Update {
+            id,
+            first,
+            last,
+            phone,
+            address,
+        } => {
+            let mut client = bd.get_record(id).ok_or(CliError::NotFound(id))?.clone();
+            if let Some(first) = first {
+                client.prenume = first;
+            }
+            if let Some(last) = last {
+                client.nume = last;
+            }
+            if let Some(phone) = phone {
+                client.nr_telefon = phone;
+            }
+            if let Some(address) = address {
+                client.adresa = address;
+            }
+            let client = Client::SCHEMA.check(&client).map_err(CliError::Invalid)?;
+            bd.update_record(id, client)?;
+            commit(&bd, &*storage)?;
+        }
+        Command::Delete { id, cascade } => {
+            let on_delete = match cascade {
+                true => OnDelete::Cascade,
+                false => OnDelete::Restrict,
+            };
+            bd.delete_record(id, on_delete)?
+                .ok_or(CliError::NotFound(id))?;
+            commit(&bd, &*storage)?;
+        }
+        Command::Orders { client } => {
+            let orders: Vec<Comanda> = match client {
+                Some(client) => bd.orders_of(client).cloned().collect(),
+                None => bd.comenzi.clone(),
+            };
+            print_records(&mut out, &orders)?;
+        }
+        Command::AddOrder {
+            client,
+            product,
+            quantity,
+            date,
+        } => {
+            let order = Comanda {
+                nr_comanda: 0,
+                nr_client: client,
+                produs: product,
+                cantitate: quantity,
+                data: date,
+            };
+            let order = Comanda::SCHEMA.check(&order).map_err(CliError::Invalid)?;
+            let id = bd.add_order(order)?;
+            commit(&bd, &*storage)?;
+            writeln!(out, "{}", id)?;
+        }
+        Command::DeleteOrder { id } => {
+            bd.delete_order(id)?.ok_or(CliError::NotFound(id))?;
+            commit(&bd, &*storage)?;
+        }
+        Command::Search {
+            field,
+            fuzzy,
+            regex,
+            pattern,
+        } => {
+            let mode = match (fuzzy, regex) {
+                (true, _) => SearchMode::Fuzzy,
+                (_, true) => SearchMode::Regex,
+                _ => SearchMode::Query,
+            };
+            let query = Query::new(mode, &pattern, field)?;
+            let clients = query_records(&bd.clienti, &query, &[]);
+            print_records(&mut out, &clients)?;
+        }
+        Command::ImportCsv {
+            file,
+            dry_run,
+            mappings,
+        } => {
+            let report = read_csv(File::open(&file)?, &mappings)?;
+            import_report(&mut bd, report, dry_run, &*storage, &mut out)?;
+        }
+        Command::ExportCsv { file, view } => {
+            let clients = view.query(&bd)?;
+            let result = match file {
+                Some(file) => write_csv(File::create(file)?, &clients),
+                None => write_csv(&mut out, &clients),
+            };
+            result.map_err(CsvError::from)?;
+        }
+        Command::ImportJson {
+            file,
+            ndjson,
+            replace,
+            cascade,
+            dry_run,
+        } => {
+            let reader = BufReader::new(File::open(&file)?);
+            let JsonDatabase {
+                clienti,
+                top,
+                normalized,
+            } = if ndjson || is_ndjson(&file) {
+                read_ndjson(reader)?
+            } else {
+                read_json(reader)?
+            };
+            for change in &normalized {
+                eprintln!("normalized {}", change);
+            }
+            // The file carries no orders, so they are checked before anything
+            // is deleted rather than failing halfway through.
+            if let (Some(order), true, false) = (bd.comenzi.first(), replace, cascade) {
+                let id = order.nr_client;
+                let orders = bd.orders_of(id).count();
+                return Err(IntegrityError::HasOrders { id, orders }.into());
+            }
+            if dry_run {
+                writeln!(out, "{} records would be imported", clienti.len())?;
+                return Ok(());
+            }
+
+            if replace {
+                let existing: Vec<i32> = bd.clienti.iter().map(|c| c.nr_ordine).collect();
+                for nr_ordine in existing {
+                    bd.delete_record(nr_ordine, OnDelete::Cascade)?;
+                }
+                for client in &clienti {
+                    bd.insert_record(client.clone())?;
+                }
+                // Ids the file had already given stay used.
+                bd.top = bd.top.max(top);
+            } else {
+                for client in &clienti {
+                    bd.add_record(client.clone())?;
+                }
+            }
+            commit(&bd, &*storage)?;
+            writeln!(out, "{} records imported", clienti.len())?;
+        }
+        Command::ExportJson { file, ndjson, view } => {
+            let clients = view.query(&bd)?;
+            let ndjson = ndjson || file.as_deref().is_some_and(is_ndjson);
+            match (file, ndjson) {
+                (Some(file), true) => write_ndjson(File::create(file)?, bd.top, &clients)?,
+                (Some(file), false) => write_json(File::create(file)?, bd.top, &clients)?,
+                (None, true) => write_ndjson(&mut out, bd.top, &clients)?,
+                (None, false) => write_json(&mut out, bd.top, &clients)?,
+            }
+        }
+        Command::Migrate { to, force } => {
+            let target = open_storage(&to, *options)?;
+            let existing = target.count()?;
+            if existing > 0 && !force {
+                return Err(CliError::Invalid(format!(
+                    "{} already holds {} records, use --force to replace them",
+                    to.display(),
+                    existing
+                )));
+            }
+            target.write(&bd)?;
+            Journal::for_database(&to).compact()?;
+            writeln!(
+                out,
+                "{} records copied to {}",
+                bd.clienti.len(),
+                to.display()
+            )?;
+        }
+        Command::ImportVcard { file, dry_run } => {
+            let report = read_vcards(&fs::read_to_string(&file)?);
+            import_report(&mut bd, report, dry_run, &*storage, &mut out)?;
+        }
+        Command::ExportVcard {
+            file,
+            ids,
+            card_version,
+            view,
+        } => {
+            let clients = if ids.is_empty() {
+                view.query(&bd)?
+            } else {
+                ids.iter()
+                    .map(|id| bd.get_record(*id).cloned().ok_or(CliError::NotFound(*id)))
+                    .collect::<Result<Vec<Client>, CliError>>()?
+            };
+            match file {
+                Some(file) => write_vcards(File::create(file)?, &clients, card_version.into())?,
+                None => write_vcards(&mut out, &clients, card_version.into())?,
+            }
+        }
+        Command::ExportAudit {
+            file,
+            from,
+            to,
+            csv,
+        } => {
+            let entries: Vec<AuditEntry> = AuditLog::for_database(db_path)
+                .entries()?
+                .into_iter()
+                .filter(|entry| entry.within(from.as_deref(), to.as_deref()))
+                .collect();
+            let writer: Box<dyn Write> = match file {
+                Some(file) => Box::new(File::create(file)?),
+                None => Box::new(&mut out),
+            };
+            match csv {
+                true => audit::write_csv(writer, &entries).map_err(CsvError::from)?,
+                false => audit::write_ndjson(writer, &entries)?,
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Adds the accepted records of an import, unless it is a dry run.
+///
+/// What was left out is printed to stderr, and rejected records
+/// make the command fail once the others are imported.
+fn import_report(
+    bd: &mut BazaDate,
+    report: ImportReport,
+    dry_run: bool,
+    storage: &dyn Storage,
+    out: &mut impl Write,
+) -> Result<(), CliError> {
+    if !report.ignored.is_empty() {
+        eprintln!("ignored: {}", report.ignored.join(", "));
+    }
+    for row in &report.rejected {
+        eprintln!("line {}: {}", row.line, row.reason);
+    }
+
+    if dry_run {
+        writeln!(out, "{} records would be imported", report.accepted.len())?;
+    } else {
+        let imported = report.accepted.len();
+        for client in report.accepted {
+            bd.add_record(client)?;
+        }
+        commit(bd, storage)?;
+        writeln!(out, "{} records imported", imported)?;
+    }
+
+    if report.rejected.is_empty() {
+        Ok(())
+    } else {
+        Err(CliError::Invalid(format!(
+            "{} records rejected",
+            report.rejected.len()
+        )))
+    }
+}
+
+/// Whether the file extension names newline-delimited JSON.
+fn is_ndjson(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|extension| extension.to_str()),
+        Some("ndjson" | "jsonl")
+    )
+}
+
+/// Saves the database and drops the journal entries the save made redundant.
+fn commit(bd: &BazaDate, storage: &dyn Storage) -> Result<(), CliError> {
+    storage.write(bd)?;
+    if let Some(journal) = &bd.journal {
+        journal.compact()?;
+    }
+    Ok(())
+}
+
+/// Prints the records as tab-separated lines, in the column order of the table.
+fn print_records<R: Record>(out: &mut impl Write, records: &[R]) -> io::Result<()> {
+    for record in records {
+        let fields: Vec<String> = R::SCHEMA
+            .values(record)
+            .iter()
+            .map(|value| escape_field(value))
+            .collect();
+        writeln!(out, "{}\t{}", record.id(), fields.join("\t"))?;
+    }
+    Ok(())
+}
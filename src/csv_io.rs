@@ -0,0 +1,190 @@
+use std::{
+    borrow::Borrow,
+    io::{self, Read, Write},
+    str::FromStr,
+};
+
+use thiserror::Error;
+
+use crate::schema::Record;
+use crate::types::{Client, ImportReport, RejectedRow};
+
+/// An explicit mapping of a header to a column, written as `HEADER=FIELD`.
+#[derive(Clone, Debug)]
+pub struct ColumnMapping {
+    pub header: String,
+    /// Index of the column in the client schema.
+    pub column: usize,
+}
+
+impl FromStr for ColumnMapping {
+    type Err = String;
+
+    fn from_str(mapping: &str) -> Result<ColumnMapping, String> {
+        let (header, field) = mapping
+            .rsplit_once('=')
+            .ok_or_else(|| format!("expected HEADER=FIELD, found `{}`", mapping))?;
+        let column = Client::SCHEMA.column(field).ok_or_else(|| {
+            format!(
+                "unknown field `{}`, expected one of {}",
+                field,
+                Client::SCHEMA.column_names()
+            )
+        })?;
+        Ok(ColumnMapping {
+            header: header.to_string(),
+            column,
+        })
+    }
+}
+
+/// Errors making a whole CSV file unusable.
+#[derive(Debug, Error)]
+pub enum CsvError {
+    #[error("no column is mapped to `{0}`")]
+    MissingColumn(&'static str),
+    #[error("columns `{first}` and `{second}` are both mapped to `{field}`")]
+    DuplicateColumn {
+        field: &'static str,
+        first: String,
+        second: String,
+    },
+    #[error(transparent)]
+    Csv(#[from] csv::Error),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+/// Reads clients from a CSV file with a header row.
+///
+/// Columns are matched to fields through `mappings` first and their header
+/// otherwise. Rows failing the schema checks are rejected, not fatal.
+pub fn read_csv(reader: impl Read, mappings: &[ColumnMapping]) -> Result<ImportReport, CsvError> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(reader);
+
+    let mut report = ImportReport::default();
+    // The schema column, CSV index and header of every imported column.
+    let mut columns: Vec<(usize, usize, String)> = Vec::new();
+    for (index, header) in reader.headers()?.iter().enumerate() {
+        let column = mappings
+            .iter()
+            .find(|mapping| mapping.header == header)
+            .map(|mapping| mapping.column)
+            .or_else(|| Client::SCHEMA.column(header));
+        match column {
+            Some(column) => {
+                if let Some((_, _, first)) = columns.iter().find(|(c, _, _)| *c == column) {
+                    return Err(CsvError::DuplicateColumn {
+                        field: Client::SCHEMA.columns[column].name,
+                        first: first.clone(),
+                        second: header.to_string(),
+                    });
+                }
+                columns.push((column, index, header.to_string()));
+            }
+            None => report.ignored.push(header.to_string()),
+        }
+    }
+    for (required, column) in Client::SCHEMA.columns.iter().enumerate() {
+        if column.required && !columns.iter().any(|(c, _, _)| *c == required) {
+            return Err(CsvError::MissingColumn(column.name));
+        }
+    }
+
+    for record in reader.records() {
+        let record = match record {
+            Ok(record) => record,
+            Err(error) => {
+                report.rejected.push(RejectedRow {
+                    line: error.position().map_or(0, |position| position.line()),
+                    reason: error.to_string(),
+                });
+                continue;
+            }
+        };
+        let line = record.position().map_or(0, |position| position.line());
+        if record.iter().all(str::is_empty) {
+            continue;
+        }
+
+        let mut values = vec![String::new(); Client::SCHEMA.columns.len()];
+        for (column, index, _) in &columns {
+            values[*column] = record.get(*index).unwrap_or_default().to_string();
+        }
+        match Client::SCHEMA.build(&values) {
+            Ok(client) => report.accepted.push(client),
+            Err(reason) => report.rejected.push(RejectedRow { line, reason }),
+        }
+    }
+
+    Ok(report)
+}
+
+/// Writes the clients as CSV, in the column order of the table.
+pub fn write_csv(
+    writer: impl Write,
+    clients: impl IntoIterator<Item = impl Borrow<Client>>,
+) -> Result<(), csv::Error> {
+    let mut writer = csv::Writer::from_writer(writer);
+    let mut header = vec!["nr_ordine"];
+    header.extend(Client::SCHEMA.columns.iter().map(|column| column.name));
+    writer.write_record(header)?;
+    for client in clients {
+        writer.write_record(Client::SCHEMA.row(client.borrow()))?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_headers_and_reports_rejected_rows() {
+        let input = "First Name,Surname,Phone,Notes,Address\n\
+                     Ion,Popescu,0722 000 000,vip,\"Str. Lunga 1, Cluj\"\n\
+                     ,Ionescu,0733,,\n\
+                     Ana,\"Pop \"\"Mica\"\"\",abc,,\n";
+        let report = read_csv(input.as_bytes(), &[]).unwrap();
+
+        assert_eq!(report.ignored, vec![String::from("Notes")]);
+        assert_eq!(report.accepted.len(), 1);
+        assert_eq!(report.accepted[0].nume, "Popescu");
+        assert_eq!(report.accepted[0].adresa, "Str. Lunga 1, Cluj");
+        assert_eq!(
+            report
+                .rejected
+                .iter()
+                .map(|row| row.line)
+                .collect::<Vec<_>>(),
+            vec![3, 4]
+        );
+    }
+
+    #[test]
+    fn export_reads_back() {
+        let clients = vec![Client {
+            nr_ordine: 7,
+            nume: String::from("Stanciu"),
+            prenume: String::from("Maria"),
+            nr_telefon: String::from("+40700123456"),
+            adresa: String::from("Bd. \"Unirii\", nr. 2"),
+        }];
+        let mut output = Vec::new();
+        write_csv(&mut output, &clients).unwrap();
+
+        let report = read_csv(output.as_slice(), &[]).unwrap();
+        assert_eq!(report.ignored, vec![String::from("nr_ordine")]);
+        assert_eq!(
+            report.accepted,
+            vec![Client {
+                nr_ordine: 0,
+                ..clients[0].clone()
+            }]
+        );
+    }
+}
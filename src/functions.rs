@@ -1,101 +1,166 @@
-use crossterm::event::{self, Event, KeyCode};
-use std::io;
+use crossterm::event::{self, Event as CEvent, KeyCode, KeyModifiers};
+use std::{
+    cmp::Reverse,
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 use tui::{
     backend::Backend,
-    layout::{Alignment, Constraint, Direction, Layout, Margin, Rect},
+    layout::{Alignment, Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
-    text::{Span, Spans, Text},
-    widgets::{Block, BorderType, Borders, Paragraph, Row, Table, TableState, Tabs},
+    text::{Span, Spans},
+    widgets::{
+        Block, BorderType, Borders, Cell, Clear, List, ListItem, Paragraph, Row, Table, TableState,
+        Tabs,
+    },
     Frame, Terminal,
 };
 
-use crate::types::{App, BazaDate, Client, InputMode, MenuItem, QueryMode, SortMode, SortOrd, RecordOption};
-
-/// Clear the terminal screen and moves the cursor at the top.
-pub fn clear_screen() {
-    print!("\x1B[2J\x1B[1;1H");
-}
-
-/// Creating the database using the `String` provided.
-pub fn create_database(file_contents: String) -> BazaDate {
-    let mut bd: BazaDate = BazaDate::new();
-    let mut count: i32 = 0;
-
-    let mut temp: Client = Client::new();
+use crate::csv_io::{read_csv, write_csv, CsvError};
+use crate::query::Query;
+use crate::schema::{Column, Record, SortKey, SortMode, TableSchema};
+use crate::types::{
+    App, Client, Comanda, Event, FileAction, FilePrompt, ImportPreview, ImportReport, InputMode,
+    IntegrityError, MenuItem, OnDelete, RecordForm, RecordOption, SaveStatus, SortOrd, Workspace,
+};
+use crate::vcard::{read_vcards, write_vcards, VcardVersion};
 
-    let mut buffer = String::new();
-    for (_, val) in file_contents.as_str().chars().enumerate() {
-        if val == '\n' {
-            match count {
-                0 => {
-                    temp.nume = buffer.clone();
-                    temp.nume = temp.nume.replace("\r", "");
-                    buffer.clear();
-                    count += 1;
-                }
-                1 => {
-                    temp.prenume = buffer.clone();
-                    temp.prenume = temp.prenume.replace("\r", "");
-                    buffer.clear();
-                    count += 1;
-                }
-                2 => {
-                    temp.nr_telefon = buffer.clone();
-                    temp.nr_telefon = temp.nr_telefon.replace("\r", "");
-                    buffer.clear();
-                    count += 1;
-                }
-                3 => {
-                    temp.adresa = buffer.clone();
-                    temp.adresa = temp.adresa.replace("\r", "");
-                    temp.nr_ordine = bd.top + 1;
-                    bd.clienti.push(temp);
-                    bd.top += 1;
-                    buffer.clear();
-                    temp = Client::new();
-                    count = 0;
-                }
-                _ => {}
-            };
-        } else {
-            buffer.push(val);
-        }
-    }
+/// How long the app waits for input before emitting a tick.
+const TICK_RATE: Duration = Duration::from_millis(250);
 
-    bd
+/// What a key press inside a record form asks for.
+enum FormAction {
+    Continue,
+    Submit,
+    Cancel,
 }
 
 /// App execution loop.
-pub fn run_app<B: Backend>(terminal: &mut Terminal<B>, mut app: &mut App) -> io::Result<()> {
+pub fn run_app<B: Backend>(
+    terminal: &mut Terminal<B>,
+    workspace: &mut Workspace,
+) -> io::Result<()> {
     let mut active_menu_item = MenuItem::Home;
     let mut active_record_option = RecordOption::None;
-    let mut table_state = TableState::default();
-    table_state.select(Some(0));
+    let mut table_states: Vec<TableState> = workspace
+        .apps
+        .iter()
+        .map(|_| TableState::default())
+        .collect();
+    let mut database_state = TableState::default();
+    database_state.select(Some(workspace.active));
     loop {
+        let active = workspace.active;
+        let database_count = workspace.apps.len();
+        let table_state = &mut table_states[active];
+        let app = &mut workspace.apps[active];
+        query_db(app);
+        match table_state.selected() {
+            _ if app.visible_ids.is_empty() => table_state.select(None),
+            Some(selected) if selected >= app.visible_ids.len() => {
+                table_state.select(Some(app.visible_ids.len() - 1))
+            }
+            None => table_state.select(Some(0)),
+            _ => {}
+        }
+
         terminal.draw(|f| {
+            let app = &mut workspace.apps[active];
             app.window_size.height = f.size().height;
             app.window_size.width = f.size().width;
-            ui(f, &app, &active_menu_item, &mut table_state);
+            ui(
+                f,
+                workspace,
+                &active_menu_item,
+                &active_record_option,
+                table_state,
+                &mut database_state,
+            );
         })?;
 
-        if let Event::Key(key) = event::read()? {
-            match app.input_mode {
+        let event = if event::poll(TICK_RATE)? {
+            match event::read()? {
+                CEvent::Key(key) => Event::Input(key),
+                _ => continue,
+            }
+        } else {
+            Event::Tick
+        };
+
+        let app = &mut workspace.apps[active];
+        let mut switch_to = None;
+        match event {
+            Event::Tick => {}
+            Event::Input(key) if app.prompt.is_some() => handle_prompt_key(app, key.code),
+            Event::Input(key) if app.import_preview.is_some() => {
+                handle_import_preview_key(app, key.code)
+            }
+            Event::Input(key) if app.order_client.is_some() => handle_order_form_key(app, key.code),
+            Event::Input(key) => match app.input_mode {
+                InputMode::Normal if active_record_option == RecordOption::Delete => {
+                    handle_delete_confirm_key(app, key.code, &mut active_record_option)
+                }
+                InputMode::Normal
+                    if active_menu_item == MenuItem::Clients
+                        && app.orders_focused
+                        && is_orders_key(key.code) =>
+                {
+                    handle_orders_key(app, key.code, table_state)
+                }
                 InputMode::Normal => match key.code {
-                    KeyCode::Char('e') => match active_menu_item {
-                        MenuItem::Clients => app.input_mode = InputMode::Editing,
-                        _ => {}
-                    },
-                    KeyCode::Char('d') | KeyCode::Backspace => match active_menu_item {
-                        MenuItem::Clients => {
-                            if let Some(selected) = table_state.selected() {
-                                app.data_base.delete_record(selected);
-                            }
+                    KeyCode::Char('e') if active_menu_item == MenuItem::Clients => {
+                        start_search(app)
+                    }
+                    KeyCode::Char('a') | KeyCode::Char('e')
+                        if active_menu_item == MenuItem::Menu =>
+                    {
+                        app.input_mode = InputMode::Editing;
+                        active_record_option = RecordOption::Add;
+                    }
+                    KeyCode::Char('d') | KeyCode::Backspace
+                        if active_menu_item == MenuItem::Clients =>
+                    {
+                        if let Some(nr_ordine) = selected_id(app, table_state) {
+                            app.delete_target = Some(nr_ordine);
+                            active_record_option = RecordOption::Delete;
+                        }
+                    }
+                    KeyCode::Enter if active_menu_item == MenuItem::Clients => {
+                        let selected_client = selected_id(app, table_state)
+                            .and_then(|nr_ordine| app.data_base.get_record(nr_ordine))
+                            .cloned();
+                        if let Some(client) = selected_client {
+                            app.edit_form = RecordForm::from_record(&client);
+                            app.edit_target = Some(client.nr_ordine);
+                            app.input_mode = InputMode::Editing;
+                            active_record_option = RecordOption::Edit;
                         }
-                        _ => {}
-                    },
+                    }
+                    KeyCode::Char('i') if active_menu_item == MenuItem::Clients => {
+                        open_prompt(app, FileAction::Import);
+                    }
+                    KeyCode::Char('x') if active_menu_item == MenuItem::Clients => {
+                        open_prompt(app, FileAction::ExportCsvView);
+                    }
+                    KeyCode::Char('X') if active_menu_item == MenuItem::Clients => {
+                        open_prompt(app, FileAction::ExportCsvAll);
+                    }
+                    KeyCode::Char('v') if active_menu_item == MenuItem::Clients => {
+                        if let Some(nr_ordine) = selected_id(app, table_state) {
+                            open_prompt(app, FileAction::ExportVcardSelected(nr_ordine));
+                        }
+                    }
+                    KeyCode::Char('V') if active_menu_item == MenuItem::Clients => {
+                        open_prompt(app, FileAction::ExportVcardAll);
+                    }
                     KeyCode::Char('q') => {
                         return Ok(());
                     }
+                    KeyCode::Char('s') => {
+                        let _ = app.save();
+                    }
                     KeyCode::Char('h') => {
                         active_menu_item = MenuItem::Home;
                     }
@@ -105,57 +170,130 @@ pub fn run_app<B: Backend>(terminal: &mut Terminal<B>, mut app: &mut App) -> io:
                     KeyCode::Char('c') => {
                         active_menu_item = MenuItem::Clients;
                     }
-                    KeyCode::Down => match active_menu_item {
-                        MenuItem::Clients => {
-                            if let Some(selected) = table_state.selected() {
-                                if selected >= app.data_base.top as usize {
-                                    table_state.select(Some(0));
-                                } else {
-                                    table_state.select(Some(selected + 1));
-                                }
-                            }
+                    KeyCode::Char('o') => {
+                        active_menu_item = MenuItem::Databases;
+                    }
+                    KeyCode::Char('l') => {
+                        app.load_audit();
+                        app.audit_selected = 0;
+                        active_menu_item = MenuItem::Log;
+                    }
+                    KeyCode::Char('e') if active_menu_item == MenuItem::Log => {
+                        app.input_mode = InputMode::Editing;
+                    }
+                    KeyCode::Down if active_menu_item == MenuItem::Log => {
+                        let count = app.visible_audit().len().max(1);
+                        app.audit_selected = (app.audit_selected + 1) % count;
+                    }
+                    KeyCode::Up if active_menu_item == MenuItem::Log => {
+                        let count = app.visible_audit().len().max(1);
+                        app.audit_selected = (app.audit_selected + count - 1) % count;
+                    }
+                    KeyCode::Down if active_menu_item == MenuItem::Databases => {
+                        let selected = database_state.selected().unwrap_or(0);
+                        database_state.select(Some((selected + 1) % database_count));
+                    }
+                    KeyCode::Up if active_menu_item == MenuItem::Databases => {
+                        let selected = database_state.selected().unwrap_or(0);
+                        database_state
+                            .select(Some((selected + database_count - 1) % database_count));
+                    }
+                    KeyCode::Enter if active_menu_item == MenuItem::Databases => {
+                        switch_to = database_state.selected();
+                    }
+                    KeyCode::Right if active_menu_item == MenuItem::Clients => {
+                        app.orders_focused = !selected_orders(app, table_state).is_empty();
+                    }
+                    KeyCode::Char('u') => app.step_history(true),
+                    KeyCode::Char('r') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        app.step_history(false)
+                    }
+                    KeyCode::Char('U') if active_menu_item == MenuItem::Clients => {
+                        app.show_history = !app.show_history;
+                    }
+                    KeyCode::Char('n') if active_menu_item == MenuItem::Clients => {
+                        if let Some(nr_ordine) = selected_id(app, table_state) {
+                            app.order_form.clear();
+                            let column = |name| Comanda::SCHEMA.column(name).expect("order column");
+                            app.order_form.values[column("nr_client")] = nr_ordine.to_string();
+                            app.order_form.values[column("cantitate")] = String::from("1");
+                            app.order_form.focus = 1;
+                            app.order_client = Some(nr_ordine);
                         }
-                        _ => {}
-                    },
-                    KeyCode::Up => match active_menu_item {
-                        MenuItem::Clients => {
-                            if let Some(selected) = table_state.selected() {
-                                if selected == 0 {
-                                    table_state.select(Some(app.data_base.top as usize));
-                                } else {
-                                    table_state.select(Some(selected - 1));
-                                }
+                    }
+                    KeyCode::Down if active_menu_item == MenuItem::Clients => {
+                        app.selected_order = 0;
+                        let count = app.visible_ids.len();
+                        table_state.select(match table_state.selected() {
+                            _ if count == 0 => None,
+                            Some(selected) if selected + 1 < count => Some(selected + 1),
+                            _ => Some(0),
+                        });
+                    }
+                    KeyCode::Up if active_menu_item == MenuItem::Clients => {
+                        app.selected_order = 0;
+                        let count = app.visible_ids.len();
+                        table_state.select(match table_state.selected() {
+                            _ if count == 0 => None,
+                            Some(selected) if selected > 0 && selected <= count => {
+                                Some(selected - 1)
                             }
+                            _ => Some(count - 1),
+                        });
+                    }
+                    KeyCode::Char('+') if active_menu_item == MenuItem::Clients => {
+                        if let Some(key) = app.sort_keys.last_mut() {
+                            key.order = SortOrd::Incr;
+                        }
+                    }
+                    KeyCode::Char('-') if active_menu_item == MenuItem::Clients => {
+                        if let Some(key) = app.sort_keys.last_mut() {
+                            key.order = SortOrd::Decr;
+                        }
+                    }
+                    KeyCode::Tab if active_menu_item == MenuItem::Clients => {
+                        if let Some(key) = app.sort_keys.last_mut() {
+                            key.mode = next_sort_mode(key.mode);
                         }
-                        _ => {}
-                    },
-                    KeyCode::Char('+') => match active_menu_item {
-                        MenuItem::Clients => app.sort_order = SortOrd::Incr,
-                        _ => {}
-                    },
-                    KeyCode::Char('-') => match active_menu_item {
-                        MenuItem::Clients => app.sort_order = SortOrd::Decr,
-                        _ => {}
-                    },
-                    KeyCode::Tab => match active_menu_item {
-                        MenuItem::Clients => {
-                            app.sort_mode = match app.sort_mode {
-                                SortMode::Id => SortMode::FirstName,
-                                SortMode::FirstName => SortMode::LastName,
-                                SortMode::LastName => SortMode::PhoneNumber,
-                                SortMode::PhoneNumber => SortMode::Address,
-                                SortMode::Address => SortMode::Id,
+                    }
+                    KeyCode::Char('>') if active_menu_item == MenuItem::Clients => {
+                        // The new key starts on the first field not sorted by yet.
+                        let mut mode = SortMode::Column(0);
+                        for _ in 0..=Client::SCHEMA.columns.len() {
+                            if app.sort_keys.iter().all(|key| key.mode != mode) {
+                                break;
                             }
+                            mode = next_sort_mode(mode);
                         }
-                        _ => {}
-                    },
+                        app.sort_keys.push(SortKey::new(mode, SortOrd::Incr));
+                    }
+                    KeyCode::Char('<')
+                        if active_menu_item == MenuItem::Clients && app.sort_keys.len() > 1 =>
+                    {
+                        app.sort_keys.pop();
+                    }
                     _ => {}
                 },
+                InputMode::Editing if active_menu_item == MenuItem::Menu => {
+                    handle_add_form_key(app, key.code, &mut active_record_option)
+                }
+                InputMode::Editing if active_record_option == RecordOption::Edit => {
+                    handle_edit_form_key(app, key.code, &mut active_record_option)
+                }
                 InputMode::Editing => match key.code {
-                    KeyCode::Char(c) => {
-                        if active_menu_item == MenuItem::Clients {
-                            app.query.push(c);
-                        }
+                    KeyCode::Char('f') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        app.search_mode = app.search_mode.next();
+                    }
+                    KeyCode::Char(c) if active_menu_item == MenuItem::Clients => {
+                        app.query.push(c);
+                    }
+                    KeyCode::Char(c) if active_menu_item == MenuItem::Log => {
+                        app.audit_filter.push(c);
+                        app.audit_selected = 0;
+                    }
+                    KeyCode::Backspace if active_menu_item == MenuItem::Log => {
+                        app.audit_filter.pop();
+                        app.audit_selected = 0;
                     }
                     KeyCode::Backspace => {
                         app.query.pop();
@@ -163,27 +301,374 @@ pub fn run_app<B: Backend>(terminal: &mut Terminal<B>, mut app: &mut App) -> io:
                     KeyCode::Esc => {
                         app.input_mode = InputMode::Normal;
                     }
-                    KeyCode::Tab => match app.query_mode {
-                        QueryMode::FirstName => app.query_mode = QueryMode::LastName,
-                        QueryMode::LastName => app.query_mode = QueryMode::PhoneNumber,
-                        QueryMode::PhoneNumber => app.query_mode = QueryMode::Address,
-                        QueryMode::Address => app.query_mode = QueryMode::FirstName,
-                    },
+                    KeyCode::Tab => {
+                        app.query_column = match app.query_column {
+                            None => Some(0),
+                            Some(index) if index + 1 < Client::SCHEMA.columns.len() => {
+                                Some(index + 1)
+                            }
+                            Some(_) => None,
+                        };
+                    }
                     _ => {}
                 },
+            },
+        }
+
+        // Checked after every event, so steady typing cannot hold off a save.
+        workspace.apps.iter_mut().for_each(App::autosave);
+
+        if let Some(index) = switch_to {
+            workspace.active = index;
+            active_menu_item = MenuItem::Clients;
+        }
+    }
+}
+
+/// Focuses the search bar. A new search starts out looking in every field,
+/// while one already typed keeps its field.
+fn start_search(app: &mut App) {
+    if app.query.is_empty() {
+        app.query_column = None;
+    }
+    app.input_mode = InputMode::Editing;
+}
+
+/// Returns the `nr_ordine` of the record on the selected row.
+fn selected_id(app: &App, table_state: &TableState) -> Option<i32> {
+    table_state
+        .selected()
+        .and_then(|selected| app.visible_ids.get(selected).copied())
+}
+
+/// Returns the orders of the client on the selected row.
+fn selected_orders<'a>(app: &'a App, table_state: &TableState) -> Vec<&'a Comanda> {
+    match selected_id(app, table_state) {
+        Some(nr_ordine) => app.data_base.orders_of(nr_ordine).collect(),
+        None => Vec::new(),
+    }
+}
+
+/// Handles a key press while the delete confirmation popup is open.
+///
+/// A client with orders is only deleted with `C`, which deletes the
+/// orders too; confirming otherwise leaves the popup open with the reason.
+fn handle_delete_confirm_key(app: &mut App, key: KeyCode, active_record_option: &mut RecordOption) {
+    let on_delete = match key {
+        KeyCode::Char('y') | KeyCode::Enter => OnDelete::Restrict,
+        KeyCode::Char('c') => OnDelete::Cascade,
+        KeyCode::Char('n') | KeyCode::Esc => {
+            app.delete_target = None;
+            *active_record_option = RecordOption::None;
+            return;
+        }
+        _ => return,
+    };
+    if let Some(nr_ordine) = app.delete_target {
+        match app.data_base.delete_record(nr_ordine, on_delete) {
+            Ok(Some(_)) => app.mark_dirty(),
+            Ok(None) => {}
+            Err(error @ IntegrityError::Io(_)) => {
+                app.save_status = SaveStatus::Failed(error.to_string())
+            }
+            Err(error) => {
+                app.message = Some(error.to_string());
+                return;
+            }
+        }
+    }
+    app.orders_focused = false;
+    app.delete_target = None;
+    *active_record_option = RecordOption::None;
+}
+
+/// Whether the key acts on the orders of the selected client while they
+/// are focused, instead of on the clients table, so `d` deletes the
+/// selected order and not its client.
+fn is_orders_key(key: KeyCode) -> bool {
+    matches!(
+        key,
+        KeyCode::Left | KeyCode::Down | KeyCode::Up | KeyCode::Char('d') | KeyCode::Backspace
+    )
+}
+
+/// Handles a key press while the orders of the selected client are focused.
+fn handle_orders_key(app: &mut App, key: KeyCode, table_state: &TableState) {
+    match key {
+        KeyCode::Left => app.orders_focused = false,
+        KeyCode::Down => {
+            let count = selected_orders(app, table_state).len().max(1);
+            app.selected_order = (app.selected_order + 1) % count;
+        }
+        KeyCode::Up => {
+            let count = selected_orders(app, table_state).len().max(1);
+            app.selected_order = (app.selected_order + count - 1) % count;
+        }
+        KeyCode::Char('d') | KeyCode::Backspace => {
+            let order = selected_orders(app, table_state)
+                .get(app.selected_order)
+                .map(|order| order.nr_comanda);
+            if let Some(nr_comanda) = order {
+                match app.data_base.delete_order(nr_comanda) {
+                    Ok(_) => {
+                        app.mark_dirty();
+                        app.message = Some(format!("Deleted order {}", nr_comanda));
+                    }
+                    Err(error) => app.save_status = SaveStatus::Failed(error.to_string()),
+                }
+            }
+            let count = selected_orders(app, table_state).len();
+            app.orders_focused = count > 0;
+            app.selected_order = app.selected_order.min(count.saturating_sub(1));
+        }
+        _ => {}
+    }
+}
+
+/// Handles a key press while the new order popup is open.
+fn handle_order_form_key(app: &mut App, key: KeyCode) {
+    let close = match handle_form_key(&mut app.order_form, key) {
+        FormAction::Continue => false,
+        FormAction::Submit => match app.order_form.submit::<Comanda>() {
+            Some(order) => match app.data_base.add_order(order) {
+                Ok(nr_comanda) => {
+                    app.mark_dirty();
+                    app.message = Some(format!("Added order {}", nr_comanda));
+                    true
+                }
+                Err(error) => {
+                    app.order_form.error = Some(error.to_string());
+                    false
+                }
+            },
+            None => false,
+        },
+        FormAction::Cancel => true,
+    };
+
+    if close {
+        app.order_form.clear();
+        app.order_client = None;
+    }
+}
+
+/// Opens the file prompt, suggesting a path next to the database.
+fn open_prompt(app: &mut App, action: FileAction) {
+    let name = match action {
+        FileAction::ExportVcardSelected(_) | FileAction::ExportVcardAll => "clients.vcf",
+        _ => "clients.csv",
+    };
+    let path = match app.storage.path().parent() {
+        Some(dir) => dir.join(name),
+        None => PathBuf::from(name),
+    };
+    app.message = None;
+    app.prompt = Some(FilePrompt {
+        action,
+        path: path.display().to_string(),
+    });
+}
+
+/// Handles a key press while the file prompt is open.
+fn handle_prompt_key(app: &mut App, key: KeyCode) {
+    let prompt = match app.prompt.as_mut() {
+        Some(prompt) => prompt,
+        None => return,
+    };
+    match key {
+        KeyCode::Char(c) => prompt.path.push(c),
+        KeyCode::Backspace => {
+            prompt.path.pop();
+        }
+        KeyCode::Esc => app.prompt = None,
+        KeyCode::Enter => {
+            let path = PathBuf::from(prompt.path.trim());
+            let action = prompt.action;
+            app.prompt = None;
+            match action {
+                FileAction::Import => match read_import(&path) {
+                    Ok(report) => app.import_preview = Some(ImportPreview { path, report }),
+                    Err(error) => app.message = Some(format!("Import failed: {}", error)),
+                },
+                _ => {
+                    let clients: Vec<Client> = match action {
+                        FileAction::ExportCsvView => app
+                            .visible_ids
+                            .iter()
+                            .filter_map(|nr_ordine| app.data_base.get_record(*nr_ordine))
+                            .cloned()
+                            .collect(),
+                        FileAction::ExportVcardSelected(nr_ordine) => app
+                            .data_base
+                            .get_record(nr_ordine)
+                            .cloned()
+                            .into_iter()
+                            .collect(),
+                        _ => app.data_base.clienti.clone(),
+                    };
+                    // vCards only fail on I/O, CSV also on encoding.
+                    let result = match action {
+                        FileAction::ExportVcardSelected(_) | FileAction::ExportVcardAll => {
+                            File::create(&path)
+                                .and_then(|file| write_vcards(file, &clients, VcardVersion::V3))
+                                .map_err(|error| error.to_string())
+                        }
+                        _ => File::create(&path)
+                            .map_err(CsvError::from)
+                            .and_then(|file| Ok(write_csv(file, &clients)?))
+                            .map_err(|error| error.to_string()),
+                    };
+                    app.message = Some(match result {
+                        Ok(()) => {
+                            format!("Exported {} records to {}", clients.len(), path.display())
+                        }
+                        Err(error) => format!("Export failed: {}", error),
+                    });
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Reads a file to import, as vCards if it ends in `.vcf` and as CSV otherwise.
+fn read_import(path: &Path) -> Result<ImportReport, CsvError> {
+    let is_vcard = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("vcf"));
+    if is_vcard {
+        Ok(read_vcards(&fs::read_to_string(path)?))
+    } else {
+        read_csv(File::open(path)?, &[])
+    }
+}
+
+/// Handles a key press while the import preview is open.
+fn handle_import_preview_key(app: &mut App, key: KeyCode) {
+    match key {
+        KeyCode::Char('y') | KeyCode::Enter => {
+            if let Some(preview) = app.import_preview.take() {
+                let mut imported = 0;
+                let label = format!("Import {}", preview.path.display());
+                let result = app.data_base.group(label, |bd| {
+                    for client in preview.report.accepted {
+                        bd.add_record(client)?;
+                        imported += 1;
+                    }
+                    Ok::<_, std::io::Error>(())
+                });
+                if let Err(error) = result {
+                    app.save_status = SaveStatus::Failed(error.to_string());
+                }
+                if imported > 0 {
+                    app.mark_dirty();
+                }
+                app.message = Some(format!(
+                    "Imported {} records from {}",
+                    imported,
+                    preview.path.display()
+                ));
+            }
+        }
+        KeyCode::Char('n') | KeyCode::Esc => app.import_preview = None,
+        _ => {}
+    }
+}
+
+/// Handles the key presses shared by every record form.
+fn handle_form_key(form: &mut RecordForm, key: KeyCode) -> FormAction {
+    match key {
+        KeyCode::Char(c) => {
+            form.focused_input_mut().push(c);
+        }
+        KeyCode::Backspace => {
+            form.focused_input_mut().pop();
+        }
+        KeyCode::Tab | KeyCode::Down => form.next(),
+        KeyCode::BackTab | KeyCode::Up => form.previous(),
+        KeyCode::Enter => return FormAction::Submit,
+        KeyCode::Esc => return FormAction::Cancel,
+        _ => {}
+    }
+    FormAction::Continue
+}
+
+/// Handles a key press while the add record form is being filled in.
+fn handle_add_form_key(app: &mut App, key: KeyCode, active_record_option: &mut RecordOption) {
+    match handle_form_key(&mut app.add_form, key) {
+        FormAction::Continue => {}
+        FormAction::Submit => {
+            if let Some(client) = app.add_form.submit::<Client>() {
+                match app.data_base.add_record(client) {
+                    Ok(_) => {
+                        app.mark_dirty();
+                        app.add_form.clear();
+                        app.input_mode = InputMode::Normal;
+                        *active_record_option = RecordOption::None;
+                    }
+                    Err(error) => app.add_form.error = Some(error.to_string()),
+                }
             }
         }
+        FormAction::Cancel => {
+            app.add_form.clear();
+            app.input_mode = InputMode::Normal;
+            *active_record_option = RecordOption::None;
+        }
+    }
+}
+
+/// Handles a key press while the edit record overlay is open.
+fn handle_edit_form_key(app: &mut App, key: KeyCode, active_record_option: &mut RecordOption) {
+    let close = match handle_form_key(&mut app.edit_form, key) {
+        FormAction::Continue => false,
+        FormAction::Submit => match (app.edit_form.submit::<Client>(), app.edit_target) {
+            (Some(client), Some(nr_ordine)) => match app.data_base.update_record(nr_ordine, client)
+            {
+                Ok(true) => {
+                    app.mark_dirty();
+                    true
+                }
+                Ok(false) => {
+                    app.edit_form.error = Some(String::from("Record no longer exists"));
+                    false
+                }
+                Err(error) => {
+                    app.edit_form.error = Some(error.to_string());
+                    false
+                }
+            },
+            (Some(_), None) => true,
+            (None, _) => false,
+        },
+        FormAction::Cancel => true,
+    };
+
+    if close {
+        app.edit_form.clear();
+        app.edit_target = None;
+        app.input_mode = InputMode::Normal;
+        *active_record_option = RecordOption::None;
     }
 }
 
 /// App user interface
 pub fn ui<B: Backend>(
     f: &mut Frame<B>,
-    app: &App,
+    workspace: &Workspace,
     active_menu_item: &MenuItem,
+    active_record_option: &RecordOption,
     table_state: &mut TableState,
+    database_state: &mut TableState,
 ) {
-    let menu_titles = vec!["Home", "Main Menu", "Clients"];
+    let app = workspace.active_app();
+    let menu_titles = [
+        "Home",
+        "Main Menu",
+        "Clients",
+        "Open Databases",
+        "Log History",
+    ];
 
     let chunks = Layout::default()
         .direction(Direction::Vertical)
@@ -221,15 +706,17 @@ pub fn ui<B: Backend>(
             ])
         })
         .collect();
-    let secondary_menus: Vec<Spans> = vec!["None", "Add", "Edit", "Delete"]
+    let secondary_menus: Vec<Spans> = ["None", "Add", "Edit", "Delete"]
         .iter()
         .map(|t| {
-            Spans::from(
-                vec![
-                    Span::styled(t.to_string(), Style::default().fg(Color::Green).add_modifier(Modifier::ITALIC))
-                ]
-            )
-        }).collect();
+            Spans::from(vec![Span::styled(
+                t.to_string(),
+                Style::default()
+                    .fg(Color::Green)
+                    .add_modifier(Modifier::ITALIC),
+            )])
+        })
+        .collect();
 
     let tabs = Tabs::new(menu)
         .select((*active_menu_item).into())
@@ -239,20 +726,19 @@ pub fn ui<B: Backend>(
         .divider(Span::raw("|"));
 
     let secondary_tabs = Tabs::new(secondary_menus)
-
-        .block(Block::default().title("Record Actions").borders(Borders::ALL))
+        .select((*active_record_option).into())
+        .block(
+            Block::default()
+                .title("Record Actions")
+                .borders(Borders::ALL),
+        )
         .style(Style::default().fg(Color::White))
         .highlight_style(Style::default().fg(Color::Black).bg(Color::White))
         .divider(Span::raw("|"));
 
     let tabs_layout = Layout::default()
         .direction(Direction::Horizontal)
-        .constraints(
-            [
-                Constraint::Min(30),
-                Constraint::Length(30)
-            ].as_ref()
-        )
+        .constraints([Constraint::Min(30), Constraint::Length(30)].as_ref())
         .split(chunks[2]);
 
     f.render_widget(tabs, tabs_layout[0]);
@@ -273,14 +759,42 @@ pub fn ui<B: Backend>(
                 .border_type(BorderType::Plain),
         );
 
-    let title = Paragraph::new("Database Management Shell")
+    let title = Paragraph::new(format!("Database Management Shell - {}", app.name))
         .style(Style::default().fg(Color::LightGreen))
         .alignment(Alignment::Center);
 
-    let mut shortcuts: Vec<Span> = Vec::new();
+    let (save_text, save_color) = match &app.save_status {
+        SaveStatus::Saved => (String::from("\u{2713} Saved"), Color::LightGreen),
+        SaveStatus::Dirty => (String::from("\u{25cf} Unsaved changes"), Color::Yellow),
+        SaveStatus::Failed(error) => (format!("\u{2717} Save failed: {}", error), Color::Red),
+    };
+    let save_indicator = Paragraph::new(Span::styled(save_text, Style::default().fg(save_color)))
+        .alignment(Alignment::Right);
+
+    let header_layout = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints(
+            [
+                Constraint::Percentage(25),
+                Constraint::Percentage(50),
+                Constraint::Percentage(25),
+            ]
+            .as_ref(),
+        )
+        .split(chunks[0]);
 
     // Rendering the previously created widgets
-    f.render_widget(title, chunks[0]);
+    if let Some(message) = &app.message {
+        f.render_widget(
+            Paragraph::new(Span::styled(
+                message.as_str(),
+                Style::default().fg(Color::LightCyan),
+            )),
+            header_layout[0],
+        );
+    }
+    f.render_widget(title, header_layout[1]);
+    f.render_widget(save_indicator, header_layout[2]);
     f.render_widget(
         Block::default()
             .borders(Borders::LEFT | Borders::TOP | Borders::RIGHT)
@@ -289,12 +803,12 @@ pub fn ui<B: Backend>(
         chunks[2],
     );
 
-    match *active_menu_item {
+    let shortcuts: Vec<Span> = match *active_menu_item {
         MenuItem::Home => {
-            shortcuts = vec![Span::styled(
-                "Q - Exit App",
-                Style::default().fg(Color::Yellow),
-            )];
+            let shortcuts = vec![
+                Span::styled("Q - Exit App | ", Style::default().fg(Color::Yellow)),
+                Span::styled("S - Save", Style::default().fg(Color::Yellow)),
+            ];
 
             f.render_widget(
                 Block::default()
@@ -303,9 +817,43 @@ pub fn ui<B: Backend>(
                 main_content_chunks[0],
             );
             f.render_widget(render_home(), main_content_chunks[1]);
+            shortcuts
         }
         MenuItem::Clients => {
-            shortcuts = if let InputMode::Normal = app.input_mode {
+            let shortcuts = if app.prompt.is_some() {
+                vec![
+                    Span::styled("Esc - Cancel | ", Style::default().fg(Color::Yellow)),
+                    Span::styled("Enter - Confirm Path", Style::default().fg(Color::Yellow)),
+                ]
+            } else if app.import_preview.is_some() {
+                vec![
+                    Span::styled("N/Esc - Cancel | ", Style::default().fg(Color::Yellow)),
+                    Span::styled(
+                        "Y/Enter - Import Accepted Rows",
+                        Style::default().fg(Color::Yellow),
+                    ),
+                ]
+            } else if app.order_client.is_some() {
+                vec![
+                    Span::styled("Esc - Cancel | ", Style::default().fg(Color::Yellow)),
+                    Span::styled("Tab - Next Field | ", Style::default().fg(Color::Yellow)),
+                    Span::styled("Enter - Save Order", Style::default().fg(Color::Yellow)),
+                ]
+            } else if app.orders_focused {
+                vec![
+                    Span::styled("Q - Exit App | ", Style::default().fg(Color::Yellow)),
+                    Span::styled(
+                        "\u{2193}/\u{2191} - Travel Through Orders | ",
+                        Style::default().fg(Color::Yellow),
+                    ),
+                    Span::styled("D - Delete Order | ", Style::default().fg(Color::Yellow)),
+                    Span::styled("N - New Order | ", Style::default().fg(Color::Yellow)),
+                    Span::styled(
+                        "\u{2190} - Back To Clients",
+                        Style::default().fg(Color::Yellow),
+                    ),
+                ]
+            } else if let InputMode::Normal = app.input_mode {
                 vec![
                     Span::styled("Q - Exit App | ", Style::default().fg(Color::Yellow)),
                     Span::styled(
@@ -316,15 +864,41 @@ pub fn ui<B: Backend>(
                         "\u{2193}/\u{2191} - Travel Through Records | ",
                         Style::default().fg(Color::Yellow),
                     ),
+                    Span::styled("Enter - Edit Record | ", Style::default().fg(Color::Yellow)),
+                    Span::styled("D - Delete Record | ", Style::default().fg(Color::Yellow)),
+                    Span::styled("N - New Order | ", Style::default().fg(Color::Yellow)),
+                    Span::styled("\u{2192} - Orders | ", Style::default().fg(Color::Yellow)),
+                    Span::styled("U - Undo | ", Style::default().fg(Color::Yellow)),
+                    Span::styled("Ctrl+R - Redo | ", Style::default().fg(Color::Yellow)),
+                    Span::styled("Shift+U - History | ", Style::default().fg(Color::Yellow)),
+                    Span::styled("I - Import | ", Style::default().fg(Color::Yellow)),
+                    Span::styled(
+                        "X/Shift+X - Export View/All to CSV | ",
+                        Style::default().fg(Color::Yellow),
+                    ),
+                    Span::styled(
+                        "V/Shift+V - Export Selected/All as vCard | ",
+                        Style::default().fg(Color::Yellow),
+                    ),
                     Span::styled(
                         "Tab - Change Sorting Mode | ",
                         Style::default().fg(Color::Yellow),
                     ),
                     Span::styled(
-                        "-/+ - Change Sorting Order",
+                        "-/+ - Change Sorting Order | ",
+                        Style::default().fg(Color::Yellow),
+                    ),
+                    Span::styled(
+                        ">/< - Add/Remove Sort Key",
                         Style::default().fg(Color::Yellow),
                     ),
                 ]
+            } else if *active_record_option == RecordOption::Edit {
+                vec![
+                    Span::styled("Esc - Cancel | ", Style::default().fg(Color::Yellow)),
+                    Span::styled("Tab - Next Field | ", Style::default().fg(Color::Yellow)),
+                    Span::styled("Enter - Save Changes", Style::default().fg(Color::Yellow)),
+                ]
             } else {
                 vec![
                     Span::styled(
@@ -332,21 +906,75 @@ pub fn ui<B: Backend>(
                         Style::default().fg(Color::Yellow),
                     ),
                     Span::styled(
-                        "Tab - Change search query",
+                        "Tab - Change search query | ",
+                        Style::default().fg(Color::Yellow),
+                    ),
+                    Span::styled(
+                        "Ctrl+F - Change Search Mode",
                         Style::default().fg(Color::Yellow),
                     ),
                 ]
             };
             render_clients(f, app, chunks[3], table_state);
+            match *active_record_option {
+                RecordOption::Edit => render_edit(f, app, chunks[3]),
+                RecordOption::Delete => render_delete(f, app, chunks[3]),
+                _ => {}
+            }
+            render_order_form(f, app, chunks[3]);
+            render_prompt(f, app, chunks[3]);
+            render_import_preview(f, app, chunks[3]);
+            shortcuts
         }
         MenuItem::Menu => {
-            shortcuts = vec![Span::styled(
-                "Q - Exit App",
-                Style::default().fg(Color::Yellow),
-            )];
+            let shortcuts = if let InputMode::Normal = app.input_mode {
+                vec![
+                    Span::styled("Q - Exit App | ", Style::default().fg(Color::Yellow)),
+                    Span::styled("S - Save | ", Style::default().fg(Color::Yellow)),
+                    Span::styled("A - Add Record", Style::default().fg(Color::Yellow)),
+                ]
+            } else {
+                vec![
+                    Span::styled("Esc - Cancel | ", Style::default().fg(Color::Yellow)),
+                    Span::styled("Tab - Next Field | ", Style::default().fg(Color::Yellow)),
+                    Span::styled("Enter - Save Record", Style::default().fg(Color::Yellow)),
+                ]
+            };
             render_add(f, app, chunks[3]);
+            shortcuts
         }
-    }
+        MenuItem::Databases => {
+            let shortcuts = vec![
+                Span::styled("Q - Exit App | ", Style::default().fg(Color::Yellow)),
+                Span::styled(
+                    "\u{2193}/\u{2191} - Travel Through Databases | ",
+                    Style::default().fg(Color::Yellow),
+                ),
+                Span::styled("Enter - Open Database", Style::default().fg(Color::Yellow)),
+            ];
+            render_databases(f, workspace, chunks[3], database_state);
+            shortcuts
+        }
+        MenuItem::Log => {
+            let shortcuts = match app.input_mode {
+                InputMode::Normal => vec![
+                    Span::styled("Q - Exit App | ", Style::default().fg(Color::Yellow)),
+                    Span::styled(
+                        "\u{2193}/\u{2191} - Travel Through Entries | ",
+                        Style::default().fg(Color::Yellow),
+                    ),
+                    Span::styled("E - Filter | ", Style::default().fg(Color::Yellow)),
+                    Span::styled("L - Reload", Style::default().fg(Color::Yellow)),
+                ],
+                InputMode::Editing => vec![Span::styled(
+                    "Esc - Exit Typing Mode",
+                    Style::default().fg(Color::Yellow),
+                )],
+            };
+            render_audit(f, app, chunks[3]);
+            shortcuts
+        }
+    };
 
     let help = Paragraph::new(vec![Spans::from(shortcuts)])
         .style(Style::default().fg(Color::LightCyan))
@@ -356,81 +984,515 @@ pub fn ui<B: Backend>(
     f.render_widget(copyright, chunks[4]);
 }
 
-/// Render the contents of the home page tab.
-pub fn render_home<'a>() -> Paragraph<'a> {
-    let home = Paragraph::new(vec![
-        Spans::from(vec![Span::raw("Welcome")]),
-        Spans::from(vec![Span::raw("")]),
-        Spans::from(vec![Span::raw("to")]),
-        Spans::from(vec![Span::raw("")]),
-        Spans::from(vec![Span::styled(
-            "DB-CLI",
-            Style::default().fg(Color::LightBlue),
-        )]),
-    ])
-    .alignment(Alignment::Center)
-    .block(
+/// Render the audit log, newest entry first, below its filter.
+fn render_audit<B: Backend>(f: &mut Frame<B>, app: &App, rendering_zone: Rect) {
+    let layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(3), Constraint::Min(1)].as_ref())
+        .split(rendering_zone);
+
+    let filter = Paragraph::new(app.audit_filter.as_str()).block(
         Block::default()
-            .borders(Borders::LEFT | Borders::BOTTOM | Borders::RIGHT)
-            .style(Style::default().fg(Color::White))
-            .border_type(BorderType::Rounded),
+            .borders(Borders::ALL)
+            .border_type(BorderType::Rounded)
+            .style(match app.input_mode {
+                InputMode::Editing => Style::default().fg(Color::Yellow),
+                InputMode::Normal => Style::default(),
+            })
+            .title("FILTER"),
     );
-    home
-}
+    f.render_widget(filter, layout[0]);
+    if let InputMode::Editing = app.input_mode {
+        f.set_cursor(
+            layout[0].x + app.audit_filter.chars().count() as u16 + 1,
+            layout[0].y + 1,
+        );
+    }
+
+    let entries = app.visible_audit();
+    let rows: Vec<Row> = entries
+        .iter()
+        .map(|entry| {
+            Row::new(vec![
+                entry.time.clone(),
+                entry.user.clone(),
+                entry.operation.clone(),
+                entry.table.clone(),
+                entry.id.to_string(),
+                entry.summary(),
+            ])
+        })
+        .collect();
+    let table = Table::new(rows)
+        .header(
+            Row::new(vec!["Time", "User", "Operation", "Table", "ID", "Changes"]).style(
+                Style::default()
+                    .fg(Color::Green)
+                    .add_modifier(Modifier::BOLD | Modifier::UNDERLINED | Modifier::ITALIC),
+            ),
+        )
+        .widths(&[
+            Constraint::Length(20),
+            Constraint::Length(10),
+            Constraint::Length(12),
+            Constraint::Length(8),
+            Constraint::Length(4),
+            Constraint::Min(30),
+        ])
+        .highlight_style(
+            Style::default()
+                .bg(Color::Rgb(147, 112, 219))
+                .fg(Color::White)
+                .add_modifier(Modifier::BOLD),
+        )
+        .highlight_symbol(">>")
+        .column_spacing(2)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_type(BorderType::Rounded)
+                .title(format!(
+                    "Audit Log ({} of {})",
+                    entries.len(),
+                    app.audit_entries.len()
+                )),
+        );
+
+    let mut state = TableState::default();
+    if !entries.is_empty() {
+        state.select(Some(app.audit_selected.min(entries.len() - 1)));
+    }
+    f.render_stateful_widget(table, layout[1], &mut state);
+}
+
+/// Render the contents of the home page tab.
+pub fn render_home<'a>() -> Paragraph<'a> {
+    let home = Paragraph::new(vec![
+        Spans::from(vec![Span::raw("Welcome")]),
+        Spans::from(vec![Span::raw("")]),
+        Spans::from(vec![Span::raw("to")]),
+        Spans::from(vec![Span::raw("")]),
+        Spans::from(vec![Span::styled(
+            "DB-CLI",
+            Style::default().fg(Color::LightBlue),
+        )]),
+    ])
+    .alignment(Alignment::Center)
+    .block(
+        Block::default()
+            .borders(Borders::LEFT | Borders::BOTTOM | Borders::RIGHT)
+            .style(Style::default().fg(Color::White))
+            .border_type(BorderType::Rounded),
+    );
+    home
+}
+
+/// Render the Add Record option zone.
+pub fn render_add<B: Backend>(f: &mut Frame<B>, app: &App, rendering_zone: Rect) {
+    let editing = matches!(app.input_mode, InputMode::Editing);
+    let form = &app.add_form;
 
-/// Render the Add Record option zone.
-pub fn render_add<B: Backend>(
-    f: &mut Frame<B>,
-    app: &App,
-    rendering_zone: Rect,
-) {
     let layout = Layout::default()
         .direction(Direction::Vertical)
         .constraints([Constraint::Percentage(100)].as_ref())
         .split(rendering_zone);
+    // Two inputs per row, in the order of the schema.
+    let rows = Client::SCHEMA.columns.len().div_ceil(2);
+    let mut constraints = vec![Constraint::Length(3); rows];
+    constraints.push(Constraint::Min(0));
     let main_layout = Layout::default()
         .direction(Direction::Vertical)
-        .vertical_margin(2)
-        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
+        .margin(2)
+        .constraints(constraints)
         .split(layout[0]);
-    let upper_layout = Layout::default()
-        .margin(3)
-        .horizontal_margin(4)
-        .direction(Direction::Horizontal)
-        .constraints([Constraint::Percentage(40), Constraint::Percentage(20), Constraint::Percentage(40)].as_ref())
-        .split(main_layout[0]);
-    let lower_layout = Layout::default()
-        .margin(3)
-        .direction(Direction::Horizontal)
-        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
-        .split(main_layout[1]);
-
-    let a = Paragraph::new("Test1")
-        .block(Block::default().borders(
-            Borders::ALL
-        ).border_type(BorderType::Rounded));
-    let b = Paragraph::new("Test2")
-        .block(Block::default().borders(
-            Borders::ALL
-        ).border_type(BorderType::Rounded));
-    let c = Paragraph::new("Test3")
-        .block(Block::default().borders(
-            Borders::ALL
-        ).border_type(BorderType::Rounded));
-    let d = Paragraph::new("Test4")
-        .block(Block::default().borders(
-            Borders::ALL
-        ).border_type(BorderType::Rounded));
+    let inputs: Vec<Rect> = main_layout[..rows]
+        .iter()
+        .flat_map(|row| {
+            Layout::default()
+                .horizontal_margin(4)
+                .direction(Direction::Horizontal)
+                .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
+                .split(*row)
+        })
+        .take(Client::SCHEMA.columns.len())
+        .collect();
 
     let border = Block::default()
-        .borders(Borders::LEFT|Borders::RIGHT|Borders::BOTTOM)
-        .border_type(BorderType::Rounded);
-
+        .borders(Borders::LEFT | Borders::RIGHT | Borders::BOTTOM)
+        .border_type(BorderType::Rounded)
+        .title(form_title(String::from("Add Record"), form));
     f.render_widget(border, layout[0]);
-    f.render_widget(a, upper_layout[0]);
-    f.render_widget(b, upper_layout[2]);
-    f.render_widget(c, lower_layout[0]);
-    f.render_widget(d, lower_layout[1]);
+
+    render_form_inputs(f, &Client::SCHEMA, form, editing, &inputs);
+}
+
+/// Render the edit record overlay on top of the clients table.
+pub fn render_edit<B: Backend>(f: &mut Frame<B>, app: &App, rendering_zone: Rect) {
+    let form = &app.edit_form;
+    let columns = Client::SCHEMA.columns.len();
+    let popup = centered_rect(60, 3 * columns as u16 + 6, rendering_zone);
+
+    let mut constraints = vec![Constraint::Length(3); columns];
+    constraints.push(Constraint::Min(0));
+    let layout = Layout::default()
+        .direction(Direction::Vertical)
+        .margin(1)
+        .constraints(constraints)
+        .split(popup);
+
+    let name = match app.edit_target {
+        Some(nr_ordine) => format!("Edit Record {}", nr_ordine),
+        None => String::from("Edit Record"),
+    };
+    let border = Block::default()
+        .borders(Borders::ALL)
+        .border_type(BorderType::Double)
+        .style(Style::default().fg(Color::White))
+        .title(form_title(name, form));
+
+    f.render_widget(Clear, popup);
+    f.render_widget(border, popup);
+    render_form_inputs(f, &Client::SCHEMA, form, true, &layout[..columns]);
+}
+
+/// Render the popup adding an order for the selected client.
+pub fn render_order_form<B: Backend>(f: &mut Frame<B>, app: &App, rendering_zone: Rect) {
+    let nr_ordine = match app.order_client {
+        Some(nr_ordine) => nr_ordine,
+        None => return,
+    };
+    let form = &app.order_form;
+    let columns = Comanda::SCHEMA.columns.len();
+    let popup = centered_rect(60, 3 * columns as u16 + 6, rendering_zone);
+
+    let mut constraints = vec![Constraint::Length(3); columns];
+    constraints.push(Constraint::Min(0));
+    let layout = Layout::default()
+        .direction(Direction::Vertical)
+        .margin(1)
+        .constraints(constraints)
+        .split(popup);
+
+    let border = Block::default()
+        .borders(Borders::ALL)
+        .border_type(BorderType::Double)
+        .style(Style::default().fg(Color::White))
+        .title(form_title(
+            format!("New Order for Client {}", nr_ordine),
+            form,
+        ));
+
+    f.render_widget(Clear, popup);
+    f.render_widget(border, popup);
+    render_form_inputs(f, &Comanda::SCHEMA, form, true, &layout[..columns]);
+}
+
+/// Render the popup asking to confirm the deletion of a record.
+pub fn render_delete<B: Backend>(f: &mut Frame<B>, app: &App, rendering_zone: Rect) {
+    let client = match app
+        .delete_target
+        .and_then(|id| app.data_base.get_record(id))
+    {
+        Some(client) => client,
+        None => return,
+    };
+    let columns = Client::SCHEMA.columns;
+    let orders = app.data_base.orders_of(client.nr_ordine).count();
+    let popup = centered_rect(
+        50,
+        columns.len() as u16 + 6 + (orders > 0) as u16,
+        rendering_zone,
+    );
+
+    let label_width = columns
+        .iter()
+        .map(|column| column.title.len())
+        .max()
+        .unwrap_or(0)
+        + 2;
+    let mut text: Vec<Spans> = columns
+        .iter()
+        .map(|column| {
+            Spans::from(vec![
+                Span::styled(
+                    format!(
+                        "{:width$}",
+                        format!("{}:", column.title),
+                        width = label_width
+                    ),
+                    Style::default().fg(Color::Green),
+                ),
+                Span::raw((column.get)(client)),
+            ])
+        })
+        .collect();
+    text.push(Spans::from(""));
+    let keys = match orders {
+        0 => String::from("Y/Enter - Delete | N/Esc - Cancel"),
+        orders => {
+            text.push(Spans::from(Span::styled(
+                format!("This client still has {} orders", orders),
+                Style::default().fg(Color::Red),
+            )));
+            String::from("C - Delete With Orders | N/Esc - Cancel")
+        }
+    };
+    text.push(Spans::from(Span::styled(
+        keys,
+        Style::default().fg(Color::Yellow),
+    )));
+
+    let confirm = Paragraph::new(text).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_type(BorderType::Double)
+            .style(Style::default().fg(Color::White))
+            .title(Span::styled(
+                format!("Delete Record {}?", client.nr_ordine),
+                Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+            )),
+    );
+
+    f.render_widget(Clear, popup);
+    f.render_widget(confirm, popup);
+}
+
+/// Render the popup asking for the path of the file to import or export.
+pub fn render_prompt<B: Backend>(f: &mut Frame<B>, app: &App, rendering_zone: Rect) {
+    let prompt = match &app.prompt {
+        Some(prompt) => prompt,
+        None => return,
+    };
+    let popup = centered_rect(60, 3, rendering_zone);
+    let title = match prompt.action {
+        FileAction::Import => "Import CSV or vCard (.vcf) from",
+        FileAction::ExportCsvView => "Export displayed records to CSV",
+        FileAction::ExportCsvAll => "Export all records to CSV",
+        FileAction::ExportVcardSelected(_) => "Export selected record as vCard",
+        FileAction::ExportVcardAll => "Export all records as vCards",
+    };
+
+    let input = Paragraph::new(prompt.path.as_str()).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_type(BorderType::Double)
+            .style(Style::default().fg(Color::Yellow))
+            .title(title),
+    );
+
+    f.render_widget(Clear, popup);
+    f.render_widget(input, popup);
+    f.set_cursor(
+        popup.x + prompt.path.chars().count() as u16 + 1,
+        popup.y + 1,
+    );
+}
+
+/// Render the report of a CSV file read for import, before it is applied.
+pub fn render_import_preview<B: Backend>(f: &mut Frame<B>, app: &App, rendering_zone: Rect) {
+    /// Rejected rows listed before the rest are only counted.
+    const SHOWN_REJECTED: usize = 8;
+
+    let preview = match &app.import_preview {
+        Some(preview) => preview,
+        None => return,
+    };
+    let report = &preview.report;
+
+    let mut text = vec![Spans::from(Span::styled(
+        format!("{} rows will be imported", report.accepted.len()),
+        Style::default().fg(Color::LightGreen),
+    ))];
+    if !report.ignored.is_empty() {
+        text.push(Spans::from(format!(
+            "Ignored: {}",
+            report.ignored.join(", ")
+        )));
+    }
+    if !report.rejected.is_empty() {
+        text.push(Spans::from(Span::styled(
+            format!("{} rows rejected:", report.rejected.len()),
+            Style::default().fg(Color::Red),
+        )));
+        for row in report.rejected.iter().take(SHOWN_REJECTED) {
+            text.push(Spans::from(format!("  line {}: {}", row.line, row.reason)));
+        }
+        if report.rejected.len() > SHOWN_REJECTED {
+            text.push(Spans::from(format!(
+                "  ... and {} more",
+                report.rejected.len() - SHOWN_REJECTED
+            )));
+        }
+    }
+    text.push(Spans::from(""));
+    text.push(Spans::from(Span::styled(
+        "Y/Enter - Import | N/Esc - Cancel",
+        Style::default().fg(Color::Yellow),
+    )));
+
+    let popup = centered_rect(60, text.len() as u16 + 2, rendering_zone);
+    let report = Paragraph::new(text).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_type(BorderType::Double)
+            .style(Style::default().fg(Color::White))
+            .title(Span::styled(
+                format!("Import {}?", preview.path.display()),
+                Style::default().add_modifier(Modifier::BOLD),
+            )),
+    );
+
+    f.render_widget(Clear, popup);
+    f.render_widget(report, popup);
+}
+
+/// Title of a record form, showing the error that is not about a single
+/// input if there is one.
+fn form_title<'a>(name: String, form: &RecordForm) -> Span<'a> {
+    match &form.error {
+        Some(error) => Span::styled(
+            format!("{} - {}", name, error),
+            Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+        ),
+        None => Span::raw(name),
+    }
+}
+
+/// Render the inputs of a record form, one per column of the schema,
+/// placing the cursor in the focused one.
+fn render_form_inputs<B: Backend, R: Record>(
+    f: &mut Frame<B>,
+    schema: &TableSchema<R>,
+    form: &RecordForm,
+    editing: bool,
+    zones: &[Rect],
+) {
+    for (index, (column, &zone)) in schema.columns.iter().zip(zones).enumerate() {
+        let focused = editing && form.focus == index;
+        let value = form.values[index].as_str();
+        let error = form.errors.get(index).and_then(Option::as_deref);
+        let title = match error {
+            Some(error) => Span::styled(
+                format!("{} - {}", column.title, error),
+                Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+            ),
+            None => Span::raw(column.title),
+        };
+        let input = Paragraph::new(value).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_type(BorderType::Rounded)
+                .title(title)
+                .style(match (focused, error.is_some()) {
+                    (_, true) => Style::default().fg(Color::Red),
+                    (true, false) => Style::default()
+                        .fg(Color::Yellow)
+                        .add_modifier(Modifier::BOLD),
+                    (false, false) => Style::default(),
+                }),
+        );
+        f.render_widget(input, zone);
+
+        if focused {
+            f.set_cursor(zone.x + value.chars().count() as u16 + 1, zone.y + 1);
+        }
+    }
+}
+
+/// Returns a rectangle of the given width percentage and height, centered in `r`.
+fn centered_rect(percent_x: u16, height: u16, r: Rect) -> Rect {
+    let vertical_margin = r.height.saturating_sub(height) / 2;
+    let popup_layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(
+            [
+                Constraint::Length(vertical_margin),
+                Constraint::Length(height),
+                Constraint::Min(0),
+            ]
+            .as_ref(),
+        )
+        .split(r);
+
+    Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints(
+            [
+                Constraint::Percentage((100 - percent_x) / 2),
+                Constraint::Percentage(percent_x),
+                Constraint::Percentage((100 - percent_x) / 2),
+            ]
+            .as_ref(),
+        )
+        .split(popup_layout[1])[1]
+}
+
+/// Render the list of open databases.
+pub fn render_databases<B: Backend>(
+    f: &mut Frame<B>,
+    workspace: &Workspace,
+    rendering_zone: Rect,
+    database_state: &mut TableState,
+) {
+    let rows: Vec<Row> = workspace
+        .apps
+        .iter()
+        .enumerate()
+        .map(|(index, app)| {
+            let status = match &app.save_status {
+                SaveStatus::Saved => String::from("Saved"),
+                SaveStatus::Dirty => String::from("Unsaved changes"),
+                SaveStatus::Failed(error) => format!("Save failed: {}", error),
+            };
+            let row = Row::new(vec![
+                if index == workspace.active {
+                    String::from("\u{25cf}")
+                } else {
+                    String::new()
+                },
+                app.name.clone(),
+                app.storage.path().display().to_string(),
+                app.data_base.clienti.len().to_string(),
+                status,
+            ]);
+            if index == workspace.active {
+                row.style(Style::default().fg(Color::LightGreen))
+            } else {
+                row
+            }
+        })
+        .collect();
+
+    let table = Table::new(rows)
+        .header(
+            Row::new(vec!["", "Name", "Path", "Records", "Status"]).style(
+                Style::default()
+                    .fg(Color::Green)
+                    .add_modifier(Modifier::BOLD | Modifier::UNDERLINED | Modifier::ITALIC),
+            ),
+        )
+        .widths(&[
+            Constraint::Length(1),
+            Constraint::Min(15),
+            Constraint::Min(30),
+            Constraint::Length(8),
+            Constraint::Min(15),
+        ])
+        .highlight_style(
+            Style::default()
+                .bg(Color::Rgb(147, 112, 219))
+                .fg(Color::White)
+                .add_modifier(Modifier::BOLD),
+        )
+        .highlight_symbol(">>")
+        .column_spacing(4)
+        .block(
+            Block::default()
+                .borders(Borders::LEFT | Borders::RIGHT | Borders::BOTTOM)
+                .border_type(BorderType::Rounded),
+        );
+
+    f.render_stateful_widget(table, rendering_zone, database_state);
 }
 
 /// Render the contents of the clients tab.
@@ -440,45 +1502,80 @@ pub fn render_clients<B: Backend>(
     rendering_zone: Rect,
     table_state: &mut TableState,
 ) {
-    let table_cell_constraints = &[
-        Constraint::Length(3),
-        Constraint::Min(10),
-        Constraint::Min(10),
-        Constraint::Min(15),
-        Constraint::Min(30),
-    ];
+    let rendering_zone = match app.show_history {
+        true => {
+            let layout = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([Constraint::Min(40), Constraint::Length(36)].as_ref())
+                .split(rendering_zone);
+            render_history(f, app, layout[1]);
+            layout[0]
+        }
+        false => rendering_zone,
+    };
+    let schema = &Client::SCHEMA;
+    let mut table_cell_constraints = vec![Constraint::Length(3)];
+    table_cell_constraints.extend(
+        schema
+            .columns
+            .iter()
+            .map(|column| Constraint::Min(column.width)),
+    );
 
     let table_layout = Layout::default()
         .direction(Direction::Vertical)
-        .constraints([Constraint::Length(3), Constraint::Min(1)].as_ref())
+        .constraints(
+            [
+                Constraint::Length(3),
+                Constraint::Min(5),
+                Constraint::Percentage(35),
+            ]
+            .as_ref(),
+        )
         .split(rendering_zone);
 
     let search_layout = Layout::default()
         .horizontal_margin(1)
         .direction(Direction::Horizontal)
-        .constraints([Constraint::Percentage(70), Constraint::Percentage(30)].as_ref())
+        .constraints([Constraint::Percentage(60), Constraint::Percentage(40)].as_ref())
         .split(table_layout[0]);
 
-    let search_title = Spans::from(vec![
-        Span::raw("Search by "),
-        match app.query_mode {
-            QueryMode::FirstName => {
-                Span::styled("FIRST NAME", Style::default().add_modifier(Modifier::BOLD))
-            }
-            QueryMode::LastName => {
-                Span::styled("LAST NAME", Style::default().add_modifier(Modifier::BOLD))
-            }
-            QueryMode::PhoneNumber => Span::styled(
-                "PHONE NUMBER",
-                Style::default().add_modifier(Modifier::BOLD),
-            ),
-            QueryMode::Address => {
-                Span::styled("ADDRESS", Style::default().add_modifier(Modifier::BOLD))
-            }
-        },
+    let mut search_title = Spans::from(vec![
+        Span::raw(format!("{} by ", app.search_mode.title())),
+        Span::styled(
+            match app.query_column {
+                Some(index) => schema.columns[index].title.to_uppercase(),
+                None => String::from("ANY FIELD"),
+            },
+            Style::default().add_modifier(Modifier::BOLD),
+        ),
     ]);
 
-    let search_bar = Paragraph::new(app.query.as_str())
+    // A query that does not parse shows the part the error is about in red.
+    let search_text = match &app.query_error {
+        Some(error) => {
+            let start = error.span.start.min(app.query.len());
+            let end = error.span.end.clamp(start, app.query.len());
+            let error_style = Style::default()
+                .fg(Color::Red)
+                .add_modifier(Modifier::UNDERLINED);
+            search_title.0.push(Span::styled(
+                format!(" - {}", error.message),
+                Style::default().fg(Color::Red),
+            ));
+            Spans::from(vec![
+                Span::raw(&app.query[..start]),
+                match start == end {
+                    true => Span::styled(" ", error_style),
+                    false => Span::styled(&app.query[start..end], error_style),
+                },
+                Span::raw(&app.query[end..]),
+            ])
+        }
+        None => Spans::from(app.query.as_str()),
+    };
+
+    let search_bar = Paragraph::new(search_text)
         .alignment(Alignment::Center)
         .style(
             Style::default()
@@ -501,31 +1598,27 @@ pub fn render_clients<B: Backend>(
                 .title_alignment(Alignment::Left),
         );
 
-    let custom_sort_text = (
-        match app.sort_order {
-            SortOrd::Incr => String::from("increasingly "),
-            SortOrd::Decr => String::from("decreasingly "),
-        },
-        match app.sort_mode {
+    // The whole sort spec, the key Tab and `+`/`-` change standing out.
+    let mut sort_bar_text = vec![Span::from("Sorted by ")];
+    for (index, key) in app.sort_keys.iter().enumerate() {
+        if index > 0 {
+            sort_bar_text.push(Span::raw(", "));
+        }
+        let name = match key.mode {
             SortMode::Id => String::from("ID"),
-            SortMode::FirstName => String::from("first name"),
-            SortMode::LastName => String::from("last name"),
-            SortMode::PhoneNumber => String::from("phone number"),
-            SortMode::Address => String::from("address"),
-        },
-    );
-    let sort_bar_text = Spans::from(vec![
-        Span::from("Sorted "),
-        Span::styled(
-            custom_sort_text.0,
-            Style::default().add_modifier(Modifier::ITALIC),
-        ),
-        Span::raw("by "),
-        Span::styled(
-            custom_sort_text.1,
-            Style::default().add_modifier(Modifier::ITALIC),
-        ),
-    ]);
+            SortMode::Column(index) => schema.columns[index].title.to_lowercase(),
+        };
+        let arrow = match key.order {
+            SortOrd::Incr => "↑",
+            SortOrd::Decr => "↓",
+        };
+        let style = match index + 1 == app.sort_keys.len() {
+            true => Style::default().add_modifier(Modifier::ITALIC | Modifier::BOLD),
+            false => Style::default().add_modifier(Modifier::ITALIC),
+        };
+        sort_bar_text.push(Span::styled(format!("{} {}", name, arrow), style));
+    }
+    let sort_bar_text = Spans::from(sort_bar_text);
 
     let sort_bar = Paragraph::new(sort_bar_text)
         .alignment(Alignment::Center)
@@ -535,24 +1628,58 @@ pub fn render_clients<B: Backend>(
                 .border_type(BorderType::Rounded),
         );
 
-    let rows = query_db(&app.query, app.query_mode, app);
+    // Searching every field, each row tells which fields matched.
+    let show_matched = app.query_column.is_none() && !app.search.is_empty();
+    let mut header: Vec<&str> = std::iter::once("ID")
+        .chain(schema.columns.iter().map(|column| column.title))
+        .collect();
+    if show_matched {
+        header.push("Matched In");
+        table_cell_constraints.push(Constraint::Min(12));
+    }
+
+    let rows: Vec<Row> = app
+        .visible_ids
+        .iter()
+        .filter_map(|nr_ordine| app.data_base.get_record(*nr_ordine))
+        .map(|client| {
+            let highlights = app.search.highlights(client);
+            let mut cells: Vec<Cell> = schema
+                .row(client)
+                .into_iter()
+                .enumerate()
+                .map(|(index, value)| {
+                    match index.checked_sub(1).map(|column| &highlights[column]) {
+                        Some(positions) if !positions.is_empty() => {
+                            Cell::from(highlight(&value, positions))
+                        }
+                        _ => Cell::from(value),
+                    }
+                })
+                .collect();
+            if show_matched {
+                let matched: Vec<&str> = schema
+                    .columns
+                    .iter()
+                    .zip(&highlights)
+                    .filter(|(_, positions)| !positions.is_empty())
+                    .map(|(column, _)| column.title)
+                    .collect();
+                cells.push(Cell::from(matched.join(", ")));
+            }
+            Row::new(cells)
+        })
+        .collect();
 
     let mut table = Table::new(rows)
         .header(
-            Row::new(vec![
-                "ID",
-                "First Name",
-                "Last Name",
-                "Phone Number",
-                "Address",
-            ])
-            .style(
+            Row::new(header).style(
                 Style::default()
                     .fg(Color::Green)
                     .add_modifier(Modifier::BOLD | Modifier::UNDERLINED | Modifier::ITALIC),
             ),
         )
-        .widths(table_cell_constraints)
+        .widths(&table_cell_constraints)
         .highlight_style(
             Style::default()
                 .bg(Color::Rgb(147, 112, 219))
@@ -573,103 +1700,311 @@ pub fn render_clients<B: Backend>(
     f.render_widget(search_bar, search_layout[0]);
     f.render_widget(sort_bar, search_layout[1]);
     f.render_stateful_widget(table, table_layout[1], table_state);
+    render_orders(f, app, table_layout[2], selected_id(app, table_state));
 }
 
-/// Function for returning only the rows with clients that
-/// match the query while also sorting them according to the sort order and mode.
-fn query_db(query: &String, query_mode: QueryMode, app: &App) -> Vec<Row<'static>> {
-    let mut sorted_clients: Vec<Client> = app.data_base.clienti.clone();
+/// The field sorted by after `mode` when cycling through them with Tab.
+fn next_sort_mode(mode: SortMode) -> SortMode {
+    match mode {
+        SortMode::Id => SortMode::Column(0),
+        SortMode::Column(index) if index + 1 < Client::SCHEMA.columns.len() => {
+            SortMode::Column(index + 1)
+        }
+        SortMode::Column(_) => SortMode::Id,
+    }
+}
 
-    sorted_clients.sort_by(|a, b| match app.sort_mode {
-        SortMode::Id => match app.sort_order {
-            SortOrd::Incr => a.nr_ordine.partial_cmp(&b.nr_ordine).unwrap(),
-            SortOrd::Decr => b.nr_ordine.partial_cmp(&a.nr_ordine).unwrap(),
-        },
-        SortMode::FirstName => match app.sort_order {
-            SortOrd::Incr => a.prenume.partial_cmp(&b.prenume).unwrap(),
-            SortOrd::Decr => b.prenume.partial_cmp(&a.prenume).unwrap(),
-        },
-        SortMode::LastName => match app.sort_order {
-            SortOrd::Incr => a.nume.partial_cmp(&b.nume).unwrap(),
-            SortOrd::Decr => b.nume.partial_cmp(&a.nume).unwrap(),
-        },
-        SortMode::PhoneNumber => match app.sort_order {
-            SortOrd::Incr => a.nr_telefon.partial_cmp(&b.nr_telefon).unwrap(),
-            SortOrd::Decr => b.nr_telefon.partial_cmp(&a.nr_telefon).unwrap(),
-        },
-        SortMode::Address => match app.sort_order {
-            SortOrd::Incr => a.adresa.partial_cmp(&b.adresa).unwrap(),
-            SortOrd::Decr => b.adresa.partial_cmp(&a.adresa).unwrap(),
-        },
+/// Splits a value into spans, the characters at `positions` standing out.
+fn highlight<'a>(value: &str, positions: &[usize]) -> Spans<'a> {
+    let style = Style::default()
+        .fg(Color::Yellow)
+        .add_modifier(Modifier::BOLD | Modifier::UNDERLINED);
+    let mut spans: Vec<Span> = Vec::new();
+    let mut run = String::new();
+    let mut highlighted = false;
+    for (index, c) in value.chars().enumerate() {
+        if positions.binary_search(&index).is_ok() != highlighted {
+            if !run.is_empty() {
+                let run = std::mem::take(&mut run);
+                spans.push(match highlighted {
+                    true => Span::styled(run, style),
+                    false => Span::raw(run),
+                });
+            }
+            highlighted = !highlighted;
+        }
+        run.push(c);
+    }
+    spans.push(match highlighted {
+        true => Span::styled(run, style),
+        false => Span::raw(run),
     });
+    Spans::from(spans)
+}
 
-    if query.is_empty() {
-        sorted_clients
-            .iter()
-            .map(|client| {
-                Row::new(vec![
-                    client.nr_ordine.to_string(),
-                    client.prenume.clone(),
-                    client.nume.clone(),
-                    client.nr_telefon.clone(),
-                    client.adresa.clone(),
-                ])
-            })
-            .collect()
-    } else {
-        match query_mode {
-            QueryMode::FirstName => sorted_clients
-                .iter()
-                .filter(|q| (**q).prenume.contains(query))
-                .map(|client| {
-                    Row::new(vec![
-                        client.nr_ordine.to_string(),
-                        client.prenume.clone(),
-                        client.nume.clone(),
-                        client.nr_telefon.clone(),
-                        client.adresa.clone(),
-                    ])
-                })
-                .collect(),
-            QueryMode::LastName => sorted_clients
-                .iter()
-                .filter(|q| (**q).nume.contains(query))
-                .map(|client| {
-                    Row::new(vec![
-                        client.nr_ordine.to_string(),
-                        client.prenume.clone(),
-                        client.nume.clone(),
-                        client.nr_telefon.clone(),
-                        client.adresa.clone(),
-                    ])
-                })
-                .collect(),
-            QueryMode::PhoneNumber => sorted_clients
-                .iter()
-                .filter(|q| (**q).nr_telefon.contains(query))
-                .map(|client| {
-                    Row::new(vec![
-                        client.nr_ordine.to_string(),
-                        client.prenume.clone(),
-                        client.nume.clone(),
-                        client.nr_telefon.clone(),
-                        client.adresa.clone(),
-                    ])
+/// Render the changes of the session, newest first, with the undone ones
+/// greyed out above them.
+fn render_history<B: Backend>(f: &mut Frame<B>, app: &App, rendering_zone: Rect) {
+    let history = &app.data_base.history;
+    let undone = history.redo.iter().map(|change| {
+        ListItem::new(change.label.clone()).style(Style::default().fg(Color::DarkGray))
+    });
+    let done = history
+        .undo
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(index, change)| {
+            let style = match index {
+                0 => Style::default()
+                    .fg(Color::Yellow)
+                    .add_modifier(Modifier::BOLD),
+                _ => Style::default(),
+            };
+            ListItem::new(change.label.clone()).style(style)
+        });
+    let items: Vec<ListItem> = undone.chain(done).collect();
+
+    let list = List::new(items).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_type(BorderType::Rounded)
+            .title(format!("History ({})", history.undo.len())),
+    );
+    f.render_widget(list, rendering_zone);
+}
+
+/// Render the orders of the selected client, below the clients table.
+fn render_orders<B: Backend>(
+    f: &mut Frame<B>,
+    app: &App,
+    rendering_zone: Rect,
+    nr_ordine: Option<i32>,
+) {
+    // Every order shown belongs to the selected client, so the column
+    // pointing to it is left out.
+    let columns: Vec<&Column<Comanda>> = Comanda::SCHEMA
+        .columns
+        .iter()
+        .filter(|column| column.references.is_none())
+        .collect();
+    let orders: Vec<&Comanda> = match nr_ordine {
+        Some(nr_ordine) => app.data_base.orders_of(nr_ordine).collect(),
+        None => Vec::new(),
+    };
+
+    let rows: Vec<Row> = orders
+        .iter()
+        .map(|order| {
+            let mut row = vec![order.nr_comanda.to_string()];
+            row.extend(columns.iter().map(|column| (column.get)(order)));
+            Row::new(row)
+        })
+        .collect();
+    let mut widths = vec![Constraint::Length(3)];
+    widths.extend(columns.iter().map(|column| Constraint::Min(column.width)));
+    let title = match nr_ordine {
+        Some(nr_ordine) => format!("Orders of Client {} ({})", nr_ordine, orders.len()),
+        None => String::from("Orders"),
+    };
+
+    let table = Table::new(rows)
+        .header(
+            Row::new(
+                std::iter::once("ID")
+                    .chain(columns.iter().map(|column| column.title))
+                    .collect::<Vec<_>>(),
+            )
+            .style(
+                Style::default()
+                    .fg(Color::Green)
+                    .add_modifier(Modifier::BOLD | Modifier::UNDERLINED | Modifier::ITALIC),
+            ),
+        )
+        .widths(&widths)
+        .highlight_style(
+            Style::default()
+                .bg(Color::Rgb(147, 112, 219))
+                .fg(Color::White)
+                .add_modifier(Modifier::BOLD),
+        )
+        .highlight_symbol(">>")
+        .column_spacing(4)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_type(BorderType::Rounded)
+                .style(match app.orders_focused {
+                    true => Style::default().fg(Color::Yellow),
+                    false => Style::default(),
                 })
-                .collect(),
-            QueryMode::Address => sorted_clients
-                .iter()
-                .filter(|q| (**q).adresa.contains(query))
-                .map(|client| {
-                    Row::new(vec![
-                        client.nr_ordine.to_string(),
-                        client.prenume.clone(),
-                        client.nume.clone(),
-                        client.nr_telefon.clone(),
-                        client.adresa.clone(),
-                    ])
+                .title(title),
+        );
+
+    let mut state = TableState::default();
+    if app.orders_focused {
+        state.select(Some(app.selected_order));
+    }
+    f.render_stateful_widget(table, rendering_zone, &mut state);
+}
+
+/// Runs the query against the database, remembering the `nr_ordine`
+/// of every row that is displayed, in display order.
+///
+/// A query that does not parse leaves the records filtered by the last one
+/// that did, so changes to them still show, and its error is shown in the
+/// search bar. The query is only built again,
+/// regular expressions included, once the search bar changes.
+fn query_db(app: &mut App) {
+    let input = (app.search_mode, app.query.clone(), app.query_column);
+    if app.searched.as_ref() != Some(&input) {
+        match Query::new(app.search_mode, &app.query, app.query_column) {
+            Ok(query) => {
+                app.search = query;
+                app.query_error = None;
+            }
+            Err(error) => app.query_error = Some(error),
+        }
+        app.searched = Some(input);
+    }
+    app.visible_ids = query_records(&app.data_base.clienti, &app.search, &app.sort_keys)
+        .iter()
+        .map(|client| client.nr_ordine)
+        .collect();
+}
+
+/// Returns the records matching the query, in the order they are displayed:
+/// the closest matches of a fuzzy search first, then as sorted.
+pub fn query_records<R: Record>(records: &[R], query: &Query<R>, sort_keys: &[SortKey]) -> Vec<R> {
+    let schema = &R::SCHEMA;
+    let mut sorted_records: Vec<(i32, R)> = records
+        .iter()
+        .filter_map(|record| Some((query.score(record)?, record.clone())))
+        .collect();
+
+    sorted_records.sort_by(|(_, a), (_, b)| schema.compare_by(sort_keys, a, b));
+    sorted_records.sort_by_key(|(score, _)| Reverse(*score));
+    sorted_records
+        .into_iter()
+        .map(|(_, record)| record)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::SearchMode;
+
+    #[test]
+    fn d_deletes_the_focused_order_and_not_its_client() {
+        let mut app = App::default();
+        let nr_client = app
+            .data_base
+            .add_record(Client {
+                nume: String::from("Stanciu"),
+                ..Client::new()
+            })
+            .unwrap();
+        for produs in ["Laptop", "Mouse"] {
+            app.data_base
+                .add_order(Comanda {
+                    nr_client,
+                    produs: String::from(produs),
+                    cantitate: 1,
+                    ..Comanda::default()
                 })
-                .collect()
+                .unwrap();
         }
+        app.visible_ids = vec![nr_client];
+        let mut table_state = TableState::default();
+        table_state.select(Some(0));
+        app.orders_focused = true;
+
+        let key = KeyCode::Char('d');
+        assert!(is_orders_key(key));
+        handle_orders_key(&mut app, key, &table_state);
+        assert!(app.data_base.get_record(nr_client).is_some());
+        assert_eq!(app.data_base.comenzi.len(), 1);
+        assert!(app.orders_focused);
+
+        handle_orders_key(&mut app, key, &table_state);
+        assert!(app.data_base.comenzi.is_empty());
+        assert!(!app.orders_focused);
+    }
+
+    #[test]
+    fn a_query_that_does_not_parse_still_follows_the_data() {
+        let mut app = App::default();
+        let ids: Vec<i32> = ["Pop", "Popescu", "Ionescu"]
+            .into_iter()
+            .map(|nume| {
+                app.data_base
+                    .add_record(Client {
+                        nume: String::from(nume),
+                        ..Client::new()
+                    })
+                    .unwrap()
+            })
+            .collect();
+        app.query = String::from("last:pop");
+        query_db(&mut app);
+        assert_eq!(app.visible_ids, [ids[0], ids[1]]);
+
+        app.query.push_str(" OR");
+        query_db(&mut app);
+        assert!(app.query_error.is_some());
+        app.data_base
+            .delete_record(ids[0], OnDelete::Restrict)
+            .unwrap();
+        query_db(&mut app);
+        assert_eq!(app.visible_ids, [ids[1]]);
+    }
+
+    #[test]
+    fn a_regex_is_only_compiled_again_when_the_search_bar_changes() {
+        let mut app = App::default();
+        let ids: Vec<i32> = ["Pop", "Popescu", "Ionescu"]
+            .into_iter()
+            .map(|nume| {
+                app.data_base
+                    .add_record(Client {
+                        nume: String::from(nume),
+                        ..Client::new()
+                    })
+                    .unwrap()
+            })
+            .collect();
+        app.search_mode = SearchMode::Regex;
+        app.query = String::from("^Pop");
+        app.query_column = Some(1);
+        query_db(&mut app);
+        assert_eq!(app.visible_ids, [ids[0], ids[1]]);
+
+        // Were the regex compiled again for this frame, it would replace
+        // the query put in its place.
+        app.search = Query::default();
+        query_db(&mut app);
+        assert_eq!(app.visible_ids, ids);
+
+        app.query.push_str("escu");
+        query_db(&mut app);
+        assert_eq!(app.visible_ids, [ids[1]]);
+    }
+
+    #[test]
+    fn a_new_search_looks_in_every_field() {
+        let mut app = App {
+            query_column: Some(2),
+            query: String::from("0722"),
+            ..App::default()
+        };
+        start_search(&mut app);
+        assert_eq!(app.query_column, Some(2));
+
+        app.input_mode = InputMode::Normal;
+        app.query.clear();
+        start_search(&mut app);
+        assert_eq!(app.query_column, None);
+        assert!(matches!(app.input_mode, InputMode::Editing));
     }
 }
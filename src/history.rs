@@ -0,0 +1,217 @@
+use crate::journal::Mutation;
+
+/// A change made to the database, along with the mutations undoing it.
+#[derive(Debug, Clone)]
+pub struct Change {
+    /// What the change did, as shown in the history panel.
+    pub label: String,
+    /// The mutations making the change, in the order they were applied.
+    pub forward: Vec<Mutation>,
+    /// The mutations undoing the change, in the order they are applied.
+    pub inverse: Vec<Mutation>,
+}
+
+impl Change {
+    /// Splits the change in two, the first part holding the first `at`
+    /// mutations and the second the rest, each with its own inverse.
+    pub fn split(mut self, at: usize) -> (Change, Change) {
+        let forward = self.forward.split_off(at);
+        let inverse = self.inverse.split_off(forward.len());
+        let second = Change {
+            label: self.label.clone(),
+            forward,
+            inverse: self.inverse,
+        };
+        let first = Change {
+            label: self.label,
+            forward: self.forward,
+            inverse,
+        };
+        (first, second)
+    }
+}
+
+/// The changes that can be undone and redone, kept for the whole session.
+#[derive(Debug, Clone, Default)]
+pub struct History {
+    /// Changes made, oldest first.
+    pub undo: Vec<Change>,
+    /// Changes undone, the most recently undone last.
+    pub redo: Vec<Change>,
+    /// The change gathering the mutations of a bulk operation.
+    open: Option<Change>,
+}
+
+/// The history belongs to the session rather than to the data, so it never
+/// makes two databases differ.
+impl PartialEq for History {
+    fn eq(&self, _other: &History) -> bool {
+        true
+    }
+}
+
+impl History {
+    /// Records a mutation along with the mutation undoing it. A new change
+    /// makes the undone ones impossible to redo.
+    pub fn push(&mut self, mutation: Mutation, inverse: Mutation) {
+        match &mut self.open {
+            Some(change) => {
+                change.forward.push(mutation);
+                change.inverse.insert(0, inverse);
+            }
+            None => self.undo.push(Change {
+                label: describe(&mutation, &inverse),
+                forward: vec![mutation],
+                inverse: vec![inverse],
+            }),
+        }
+        self.redo.clear();
+    }
+
+    /// Starts gathering mutations into a single change. Returns `false` if
+    /// a change is already being gathered, which then takes them instead.
+    pub fn begin(&mut self, label: String) -> bool {
+        if self.open.is_some() {
+            return false;
+        }
+        self.open = Some(Change {
+            label,
+            forward: Vec::new(),
+            inverse: Vec::new(),
+        });
+        true
+    }
+
+    /// Puts back the parts of a change being undone or redone: `undo` as
+    /// the next change to undo and `redo` as the next to redo. Empty parts
+    /// are dropped.
+    pub fn keep(&mut self, undo: Change, redo: Change) {
+        if !undo.forward.is_empty() {
+            self.undo.push(undo);
+        }
+        if !redo.forward.is_empty() {
+            self.redo.push(redo);
+        }
+    }
+
+    /// Ends the change started by `begin`, keeping it if anything changed.
+    pub fn end(&mut self) {
+        if let Some(change) = self.open.take() {
+            if !change.forward.is_empty() {
+                self.undo.push(change);
+            }
+        }
+    }
+}
+
+/// Label of a change made of a single mutation.
+fn describe(mutation: &Mutation, inverse: &Mutation) -> String {
+    let name = |mutation: &Mutation| match mutation {
+        Mutation::Add(client) | Mutation::Update(client) => {
+            format!(" ({} {})", client.prenume, client.nume)
+        }
+        _ => String::new(),
+    };
+    match mutation {
+        Mutation::Add(client) => format!("Add client {}{}", client.nr_ordine, name(mutation)),
+        Mutation::Update(client) => format!("Edit client {}{}", client.nr_ordine, name(inverse)),
+        Mutation::Delete(nr_ordine) => format!("Delete client {}{}", nr_ordine, name(inverse)),
+        Mutation::AddOrder(order) => format!(
+            "Add order {} for client {}",
+            order.nr_comanda, order.nr_client
+        ),
+        Mutation::DeleteOrder(nr_comanda) => format!("Delete order {}", nr_comanda),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::journal::Journal;
+    use crate::test_support::{add_clients, TestDir};
+    use crate::types::{BazaDate, Client, Comanda, OnDelete};
+
+    #[test]
+    fn undo_and_redo_walk_through_every_change() {
+        let mut bd = BazaDate::new();
+        let client = Client {
+            prenume: String::from("Ion"),
+            nume: String::from("Pop"),
+            ..Client::new()
+        };
+        let nr_ordine = bd.add_record(client.clone()).unwrap();
+        bd.update_record(
+            nr_ordine,
+            Client {
+                adresa: String::from("Cluj"),
+                ..client.clone()
+            },
+        )
+        .unwrap();
+        bd.add_order(Comanda {
+            nr_client: nr_ordine,
+            produs: String::from("Laptop"),
+            cantitate: 1,
+            ..Comanda::default()
+        })
+        .unwrap();
+        let edited = bd.clone();
+        bd.delete_record(nr_ordine, OnDelete::Cascade).unwrap();
+
+        let labels: Vec<&str> = bd
+            .history
+            .undo
+            .iter()
+            .map(|change| change.label.as_str())
+            .collect();
+        assert_eq!(
+            labels,
+            [
+                "Add client 0 (Ion Pop)",
+                "Edit client 0 (Ion Pop)",
+                "Add order 0 for client 0",
+                "Delete client 0 with 1 orders",
+            ]
+        );
+
+        bd.undo().unwrap();
+        assert_eq!(bd.comenzi, edited.comenzi);
+        assert_eq!(bd.clienti, edited.clienti);
+        while bd.undo().unwrap().is_some() {}
+        assert!(bd.clienti.is_empty() && bd.comenzi.is_empty());
+
+        while bd.redo().unwrap().is_some() {}
+        assert!(bd.clienti.is_empty() && bd.comenzi.is_empty());
+        assert_eq!(bd.history.undo.len(), 4);
+        assert_eq!(bd.top, 0);
+    }
+
+    #[test]
+    fn a_change_that_fails_to_undo_stays_in_the_history() {
+        let mut bd = BazaDate::new();
+        bd.group(String::from("Import"), |bd| {
+            add_clients(bd, &["a", "b", "c"]);
+        });
+        let imported = bd.clone();
+
+        let dir = TestDir::new("history");
+        let missing = dir.path().join("missing").join("baza_de_date.io");
+        bd.journal = Some(Journal::for_database(&missing));
+        assert!(bd.undo().is_err());
+        assert_eq!(bd.clienti, imported.clienti);
+        assert_eq!(bd.history.undo.len(), 1);
+        assert!(bd.history.redo.is_empty());
+
+        bd.journal = None;
+        assert_eq!(bd.undo().unwrap().as_deref(), Some("Import"));
+        assert!(bd.clienti.is_empty());
+
+        // Had the undo stopped after one mutation, the client undone would
+        // be redone on its own and the two others undone on their own.
+        let change = bd.history.redo.pop().unwrap();
+        let (rest, undone) = change.split(2);
+        assert_eq!(rest.forward.len(), 2);
+        assert_eq!(undone.inverse, [Mutation::Delete(2)]);
+        assert_eq!(rest.inverse, [Mutation::Delete(1), Mutation::Delete(0)]);
+    }
+}
@@ -0,0 +1,284 @@
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::storage::{
+    decode_fields, encode_fields, field_columns, field_names, open_for_append, sibling_path,
+    DatabaseError, LEGACY_FIELDS,
+};
+use crate::types::{BazaDate, Client, Comanda};
+
+/// A change made to the database through `BazaDate`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Mutation {
+    /// A new record, already carrying its `nr_ordine`.
+    Add(Client),
+    /// New fields for the record with the same `nr_ordine`.
+    Update(Client),
+    /// Removal of the record with the given `nr_ordine`.
+    Delete(i32),
+    /// A new order, already carrying its `nr_comanda`.
+    AddOrder(Comanda),
+    /// Removal of the order with the given `nr_comanda`.
+    DeleteOrder(i32),
+}
+
+impl Mutation {
+    /// The name of the operation, as written in the journal and the audit log.
+    pub fn operation(&self) -> &'static str {
+        match self {
+            Mutation::Add(_) => "add",
+            Mutation::Update(_) => "update",
+            Mutation::Delete(_) => "delete",
+            Mutation::AddOrder(_) => "add-order",
+            Mutation::DeleteOrder(_) => "delete-order",
+        }
+    }
+}
+
+/// Append-only log of the mutations made since the last snapshot.
+///
+/// Every line holds the sequence number of the mutation, its kind and the
+/// `nr_ordine` it applies to, followed by the escaped fields for adds and
+/// updates. Entries are flushed to disk before the mutation is applied.
+///
+/// A new journal starts with `fields` and `order_fields` lines naming the
+/// stored columns; journals without them use the legacy field order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Journal {
+    path: PathBuf,
+}
+
+impl Journal {
+    /// The journal kept next to the database at `db_path`.
+    pub fn for_database(db_path: &Path) -> Journal {
+        Journal {
+            path: sibling_path(db_path, ".journal"),
+        }
+    }
+
+    /// Appends a mutation and waits for it to reach the disk.
+    pub fn append(&self, seq: u64, mutation: &Mutation) -> io::Result<()> {
+        let operation = mutation.operation();
+        let line = match mutation {
+            Mutation::Add(client) | Mutation::Update(client) => format!(
+                "{}\t{}\t{}\t{}\n",
+                seq,
+                operation,
+                client.nr_ordine,
+                encode_fields(client)
+            ),
+            Mutation::AddOrder(order) => format!(
+                "{}\t{}\t{}\t{}\n",
+                seq,
+                operation,
+                order.nr_comanda,
+                encode_fields(order)
+            ),
+            Mutation::Delete(id) | Mutation::DeleteOrder(id) => {
+                format!("{}\t{}\t{}\n", seq, operation, id)
+            }
+        };
+
+        let mut file = open_for_append(&self.path)?;
+        if file.metadata()?.len() == 0 {
+            let header = format!(
+                "fields\t{}\norder_fields\t{}\n",
+                field_names::<Client>().join("\t"),
+                field_names::<Comanda>().join("\t")
+            );
+            file.write_all(header.as_bytes())?;
+        }
+        file.write_all(line.as_bytes())?;
+        file.sync_data()
+    }
+
+    /// Reads every complete entry of the journal, oldest first.
+    ///
+    /// A last line without its newline was cut off by a crash while it was
+    /// being written, so its mutation was never applied and it is skipped.
+    pub fn entries(&self) -> Result<Vec<(u64, Mutation)>, DatabaseError> {
+        let contents = match fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(error) => return Err(error.into()),
+        };
+
+        let complete = match contents.rfind('\n') {
+            Some(end) => &contents[..=end],
+            None => "",
+        };
+
+        let mut columns = Columns {
+            clienti: field_columns::<Client>(LEGACY_FIELDS)?,
+            comenzi: field_columns::<Comanda>(field_names::<Comanda>())?,
+        };
+        let mut entries = Vec::new();
+        for (index, line) in complete.lines().enumerate() {
+            if let Some(names) = line.strip_prefix("fields\t") {
+                columns.clienti = field_columns::<Client>(names.split('\t'))?;
+            } else if let Some(names) = line.strip_prefix("order_fields\t") {
+                columns.comenzi = field_columns::<Comanda>(names.split('\t'))?;
+            } else if !line.is_empty() {
+                let entry =
+                    parse_entry(line, &columns).map_err(|message| DatabaseError::Journal {
+                        line: index + 1,
+                        message,
+                    })?;
+                entries.push(entry);
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Applies the entries newer than the snapshot the database was read from.
+    pub fn replay(&self, bd: &mut BazaDate) -> Result<(), DatabaseError> {
+        for (seq, mutation) in self.entries()? {
+            if seq > bd.seq {
+                bd.apply(mutation);
+                bd.seq = seq;
+            }
+        }
+        Ok(())
+    }
+
+    /// Empties the journal once every entry is part of a saved snapshot.
+    pub fn compact(&self) -> io::Result<()> {
+        File::create(&self.path)?.sync_all()
+    }
+}
+
+/// The order in which a journal stores the fields of each table.
+struct Columns {
+    clienti: Vec<usize>,
+    comenzi: Vec<usize>,
+}
+
+/// Parses one journal line whose fields are stored in the order of `columns`.
+fn parse_entry(line: &str, columns: &Columns) -> Result<(u64, Mutation), String> {
+    let values: Vec<&str> = line.split('\t').collect();
+    if values.len() < 3 {
+        return Err(String::from("truncated entry"));
+    }
+
+    let seq = values[0]
+        .parse::<u64>()
+        .map_err(|_| format!("invalid sequence number `{}`", values[0]))?;
+    let nr_ordine = values[2]
+        .parse::<i32>()
+        .map_err(|_| format!("invalid record id `{}`", values[2]))?;
+
+    let mutation = match values[1] {
+        "add" | "update" => {
+            let mut client: Client = decode_fields(&values[3..], &columns.clienti)?;
+            client.nr_ordine = nr_ordine;
+            if values[1] == "add" {
+                Mutation::Add(client)
+            } else {
+                Mutation::Update(client)
+            }
+        }
+        "delete" => Mutation::Delete(nr_ordine),
+        "add-order" => {
+            let mut order: Comanda = decode_fields(&values[3..], &columns.comenzi)?;
+            order.nr_comanda = nr_ordine;
+            Mutation::AddOrder(order)
+        }
+        "delete-order" => Mutation::DeleteOrder(nr_ordine),
+        other => return Err(format!("unknown operation `{}`", other)),
+    };
+
+    Ok((seq, mutation))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{FileStorage, Storage};
+    use crate::test_support::{add_clients, save_options, TestDir};
+    use crate::types::OnDelete;
+
+    #[test]
+    fn replays_mutations_made_after_the_last_save() {
+        let dir = TestDir::new("journal");
+        let storage = FileStorage::new(&dir.database(), save_options());
+        let mut bd = storage.load().unwrap();
+        let first = bd
+            .add_record(Client {
+                nume: String::from("Stanciu"),
+                ..Client::new()
+            })
+            .unwrap();
+        storage.write(&bd).unwrap();
+
+        let second = bd
+            .add_record(Client {
+                nume: String::from("Popescu\tIon"),
+                ..Client::new()
+            })
+            .unwrap();
+        bd.update_record(
+            first,
+            Client {
+                nume: String::from("Stanciu"),
+                adresa: String::from("str. 1\nbl. 2"),
+                ..Client::new()
+            },
+        )
+        .unwrap();
+        bd.delete_record(second, OnDelete::Restrict).unwrap();
+
+        assert_eq!(storage.load().unwrap(), bd);
+    }
+
+    #[test]
+    fn replayed_mutations_find_their_record_after_a_delete_and_save() {
+        let dir = TestDir::new("journal-ids");
+        let storage = FileStorage::new(&dir.database(), save_options());
+        let mut bd = storage.load().unwrap();
+        let ids = add_clients(&mut bd, &["a", "b", "c"]);
+        bd.delete_record(ids[0], OnDelete::Restrict).unwrap();
+        storage.write(&bd).unwrap();
+
+        // Once the first record is gone the last one no longer sits at its
+        // id's position, so the journal must still reach it by id.
+        bd.update_record(
+            ids[2],
+            Client {
+                nume: String::from("c2"),
+                ..Client::new()
+            },
+        )
+        .unwrap();
+        bd.delete_record(ids[1], OnDelete::Restrict).unwrap();
+
+        let loaded = storage.load().unwrap();
+        assert_eq!(loaded, bd);
+        assert_eq!(loaded.get_record(ids[2]).unwrap().nume, "c2");
+        assert!(loaded.get_record(ids[1]).is_none());
+    }
+
+    #[test]
+    fn appending_after_a_torn_line_keeps_the_journal_readable() {
+        let dir = TestDir::new("journal-torn");
+        let storage = FileStorage::new(&dir.database(), save_options());
+        let mut bd = storage.load().unwrap();
+        add_clients(&mut bd, &["a"]);
+
+        // A crash while appending the next entry leaves half of it behind.
+        let journal = bd.journal.clone().unwrap();
+        let mut file = fs::OpenOptions::new()
+            .append(true)
+            .open(&journal.path)
+            .unwrap();
+        file.write_all(b"2\tadd\t1\tb").unwrap();
+        drop(file);
+
+        let mut bd = storage.load().unwrap();
+        add_clients(&mut bd, &["c"]);
+        assert_eq!(journal.entries().unwrap().len(), 2);
+        assert_eq!(storage.load().unwrap(), bd);
+    }
+}
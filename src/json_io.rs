@@ -0,0 +1,360 @@
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, Read, Write},
+};
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+use thiserror::Error;
+
+use crate::schema::Record;
+use crate::types::Client;
+
+/// Name identifying the documents written by this module.
+pub const SCHEMA: &str = "db_cli-rs/clients";
+/// Version of the schema written by this module.
+pub const SCHEMA_VERSION: u64 = 1;
+
+/// Errors making a JSON file unusable, pointing to the offending record.
+#[derive(Debug, Error)]
+pub enum JsonError {
+    #[error("missing `{0}`")]
+    MissingKey(&'static str),
+    #[error("unknown key `{0}`")]
+    UnknownKey(String),
+    #[error("unsupported schema `{0}`, expected `{SCHEMA}`")]
+    UnsupportedSchema(String),
+    #[error("unsupported schema version {0}")]
+    UnsupportedVersion(u64),
+    #[error("{location}: {message}")]
+    Record { location: String, message: String },
+    #[error(transparent)]
+    Syntax(#[from] serde_json::Error),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+/// The records of a JSON file, checked against the schema.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonDatabase {
+    pub top: i32,
+    pub clienti: Vec<Client>,
+    /// The values stored differently than written in the file, such as
+    /// names with surrounding spaces, like `record 0: Last Name " Pop " -> "Pop"`.
+    pub normalized: Vec<String>,
+}
+
+/// The keys written before the records.
+#[derive(Serialize)]
+struct Header {
+    schema: &'static str,
+    version: u64,
+    top: i32,
+}
+
+impl Header {
+    fn new(top: i32) -> Header {
+        Header {
+            schema: SCHEMA,
+            version: SCHEMA_VERSION,
+            top,
+        }
+    }
+}
+
+/// A whole database as a single JSON document.
+#[derive(Serialize)]
+struct Document<'a> {
+    #[serde(flatten)]
+    header: Header,
+    clienti: &'a [Client],
+}
+
+/// Writes the clients as one pretty-printed JSON document.
+pub fn write_json(mut writer: impl Write, top: i32, clients: &[Client]) -> Result<(), JsonError> {
+    let document = Document {
+        header: Header::new(top),
+        clienti: clients,
+    };
+    serde_json::to_writer_pretty(&mut writer, &document)?;
+    writeln!(writer)?;
+    Ok(())
+}
+
+/// Writes the clients as newline-delimited JSON, after a header line.
+pub fn write_ndjson(mut writer: impl Write, top: i32, clients: &[Client]) -> Result<(), JsonError> {
+    serde_json::to_writer(&mut writer, &Header::new(top))?;
+    writeln!(writer)?;
+    for client in clients {
+        serde_json::to_writer(&mut writer, client)?;
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+/// Reads a document written by `write_json`.
+pub fn read_json(reader: impl Read) -> Result<JsonDatabase, JsonError> {
+    let document: Value = serde_json::from_reader(reader)?;
+    let object = document.as_object().ok_or_else(|| JsonError::Record {
+        location: String::from("document"),
+        message: String::from("expected an object"),
+    })?;
+    let top = check_header(object, &["clienti"])?;
+
+    let records = match object.get("clienti") {
+        Some(Value::Array(records)) => records,
+        Some(_) => {
+            return Err(JsonError::Record {
+                location: String::from("clienti"),
+                message: String::from("expected an array"),
+            })
+        }
+        None => return Err(JsonError::MissingKey("clienti")),
+    };
+
+    let clienti = records
+        .iter()
+        .enumerate()
+        .map(|(index, record)| {
+            serde_json::from_value::<Client>(record.clone()).map_err(|error| JsonError::Record {
+                location: format!("record {}", index),
+                message: error.to_string(),
+            })
+        })
+        .collect::<Result<Vec<Client>, JsonError>>()?;
+
+    validate(top, clienti, |index| format!("record {}", index))
+}
+
+/// Reads a file written by `write_ndjson`. Blank lines are skipped.
+pub fn read_ndjson(reader: impl BufRead) -> Result<JsonDatabase, JsonError> {
+    let mut top = None;
+    let mut clienti = Vec::new();
+    let mut lines = Vec::new();
+
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        let number = index + 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let location = || format!("line {}", number);
+
+        match top {
+            None => {
+                let header: Value =
+                    serde_json::from_str(&line).map_err(|error| line_error(location(), error))?;
+                let object = header.as_object().ok_or_else(|| JsonError::Record {
+                    location: location(),
+                    message: String::from("expected the header object"),
+                })?;
+                top = Some(check_header(object, &[])?);
+            }
+            Some(_) => {
+                let client = serde_json::from_str::<Client>(&line)
+                    .map_err(|error| line_error(location(), error))?;
+                clienti.push(client);
+                lines.push(number);
+            }
+        }
+    }
+
+    let top = top.ok_or(JsonError::MissingKey("schema"))?;
+    validate(top, clienti, |index| format!("line {}", lines[index]))
+}
+
+/// Reports an error parsing one line of NDJSON, where the position
+/// `serde_json` gives is relative to the line.
+fn line_error(location: String, error: serde_json::Error) -> JsonError {
+    let message = error.to_string();
+    let suffix = format!(" at line {} column {}", error.line(), error.column());
+    let message = match message.strip_suffix(&suffix) {
+        Some(message) => format!("{} (column {})", message, error.column()),
+        None => message,
+    };
+    JsonError::Record { location, message }
+}
+
+/// Checks the schema name and version, returning `top`.
+///
+/// Keys other than the header ones and `allowed` are rejected, so that
+/// a newer file is never read as if nothing had changed.
+fn check_header(object: &Map<String, Value>, allowed: &[&str]) -> Result<i32, JsonError> {
+    for key in object.keys() {
+        if !["schema", "version", "top"].contains(&key.as_str()) && !allowed.contains(&key.as_str())
+        {
+            return Err(JsonError::UnknownKey(key.clone()));
+        }
+    }
+
+    match object.get("schema") {
+        Some(Value::String(schema)) if schema == SCHEMA => {}
+        Some(Value::String(schema)) => return Err(JsonError::UnsupportedSchema(schema.clone())),
+        Some(other) => return Err(JsonError::UnsupportedSchema(other.to_string())),
+        None => return Err(JsonError::MissingKey("schema")),
+    }
+    match object.get("version").map(Value::as_u64) {
+        Some(Some(SCHEMA_VERSION)) => {}
+        Some(Some(version)) => return Err(JsonError::UnsupportedVersion(version)),
+        Some(None) => {
+            return Err(JsonError::Record {
+                location: String::from("version"),
+                message: String::from("expected a positive integer"),
+            })
+        }
+        None => return Err(JsonError::MissingKey("version")),
+    }
+    match object.get("top") {
+        Some(top) => top
+            .as_i64()
+            .and_then(|top| i32::try_from(top).ok())
+            .filter(|top| *top >= -1)
+            .ok_or_else(|| JsonError::Record {
+                location: String::from("top"),
+                message: format!("expected an id or -1, found {}", top),
+            }),
+        None => Err(JsonError::MissingKey("top")),
+    }
+}
+
+/// Checks every record against the client schema, and that the ids are
+/// unique and no greater than `top`.
+///
+/// Values are stored the way the add form would store them, as in the CSV
+/// and vCard imports, and every value changed is reported. Files written
+/// before a rule was added can then still be imported.
+fn validate(
+    top: i32,
+    clienti: Vec<Client>,
+    location: impl Fn(usize) -> String,
+) -> Result<JsonDatabase, JsonError> {
+    let mut seen = HashMap::new();
+    let mut checked = Vec::with_capacity(clienti.len());
+    let mut normalized = Vec::new();
+
+    for (index, client) in clienti.into_iter().enumerate() {
+        let error = |message: String| JsonError::Record {
+            location: location(index),
+            message,
+        };
+        if client.nr_ordine < 0 || client.nr_ordine > top {
+            return Err(error(format!(
+                "nr_ordine {} is outside 0..={}",
+                client.nr_ordine, top
+            )));
+        }
+        if let Some(first) = seen.insert(client.nr_ordine, index) {
+            return Err(error(format!(
+                "nr_ordine {} is also used by {}",
+                client.nr_ordine,
+                location(first)
+            )));
+        }
+        let stored = Client::SCHEMA.check(&client).map_err(error)?;
+        for column in Client::SCHEMA.columns {
+            let (value, stored_value) = ((column.get)(&client), (column.get)(&stored));
+            if value != stored_value {
+                normalized.push(format!(
+                    "{}: {} {:?} -> {:?}",
+                    location(index),
+                    column.title,
+                    value,
+                    stored_value
+                ));
+            }
+        }
+        checked.push(stored);
+    }
+
+    Ok(JsonDatabase {
+        top,
+        clienti: checked,
+        normalized,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clients() -> Vec<Client> {
+        vec![
+            Client {
+                nr_ordine: 0,
+                nume: String::from("Popescu"),
+                prenume: String::from("Ion"),
+                nr_telefon: String::from("0722123456"),
+                adresa: String::from("Str. \"Lunga\"\n1"),
+            },
+            Client {
+                nr_ordine: 4,
+                nume: String::from("Stanciu"),
+                prenume: String::from("Maria"),
+                nr_telefon: String::new(),
+                adresa: String::new(),
+            },
+        ]
+    }
+
+    #[test]
+    fn both_formats_round_trip() {
+        let mut document = Vec::new();
+        write_json(&mut document, 5, &clients()).unwrap();
+        let mut lines = Vec::new();
+        write_ndjson(&mut lines, 5, &clients()).unwrap();
+
+        let expected = JsonDatabase {
+            top: 5,
+            clienti: clients(),
+            normalized: Vec::new(),
+        };
+        assert_eq!(read_json(document.as_slice()).unwrap(), expected);
+        assert_eq!(read_ndjson(lines.as_slice()).unwrap(), expected);
+    }
+
+    #[test]
+    fn errors_point_to_the_record() {
+        let input = format!(
+            "{{\"schema\":\"{}\",\"version\":1,\"top\":3}}\n\
+             {{\"nr_ordine\":0,\"nume\":\"Pop\",\"prenume\":\"Ana\",\"nr_telefon\":\"\",\"adresa\":\"\"}}\n\
+             \n\
+             {{\"nr_ordine\":1,\"nume\":\"Pop\",\"prenume\":\"\",\"nr_telefon\":\"\",\"adresa\":\"\"}}\n",
+            SCHEMA
+        );
+        let error = read_ndjson(input.as_bytes()).unwrap_err();
+        assert_eq!(error.to_string(), "line 4: First Name is required");
+
+        let input = format!(
+            "{{\"schema\":\"{}\",\"version\":2,\"top\":-1,\"clienti\":[]}}",
+            SCHEMA
+        );
+        assert!(matches!(
+            read_json(input.as_bytes()),
+            Err(JsonError::UnsupportedVersion(2))
+        ));
+    }
+
+    #[test]
+    fn exports_of_values_stored_before_a_rule_import_normalized() {
+        let old = Client {
+            nr_ordine: 0,
+            nume: String::from(" Pop "),
+            prenume: String::from("Ana"),
+            nr_telefon: String::from("001112324152"),
+            adresa: String::new(),
+        };
+        let mut document = Vec::new();
+        write_json(&mut document, 0, std::slice::from_ref(&old)).unwrap();
+
+        let imported = read_json(document.as_slice()).unwrap();
+        assert_eq!(imported.clienti, [Client::SCHEMA.check(&old).unwrap()]);
+        assert_eq!(imported.clienti[0].nume, "Pop");
+        assert_eq!(
+            imported.normalized,
+            [
+                "record 0: Last Name \" Pop \" -> \"Pop\"",
+                "record 0: Phone Number \"001112324152\" -> \"+1112324152\"",
+            ]
+        );
+    }
+}
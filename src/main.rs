@@ -1,7 +1,24 @@
+mod audit;
+mod cli;
+mod csv_io;
 mod functions;
+mod history;
+mod journal;
+mod json_io;
+mod matching;
+mod query;
+mod schema;
+mod sqlite;
+mod storage;
+#[cfg(test)]
+mod test_support;
 mod types;
+mod vcard;
 
-use functions::{create_database, run_app};
+use clap::Parser;
+use cli::Cli;
+use functions::run_app;
+use storage::{open_storage, SaveOptions};
 
 use tui::{backend::CrosstermBackend, Terminal};
 
@@ -10,27 +27,46 @@ use crossterm::{
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
-use types::App;
+use types::{App, Workspace};
 
-use std::{
-    fs::{self, File, OpenOptions},
-    io,
-    io::{Read, Write},
-    path::Path,
-};
+use std::{io, process};
 
 fn main() -> Result<(), std::io::Error> {
-    // INITIALIZE THE DATABASE
-    if !Path::new("./baza_de_date.io").exists() {
-        let file = File::create("./baza_de_date.io").expect("Can't Create File");
-    }
+    let cli = Cli::parse();
+    let databases = cli.database_specs();
+    let save_options = SaveOptions::from_env();
 
-    let mut file = File::open("./baza_de_date.io").expect("Can't Open File");
-    let mut file_contents: String = String::new();
-    file.read_to_string(&mut file_contents)
-        .expect("Couldn't read file.");
+    // NON-INTERACTIVE SUBCOMMANDS
+    if let Some(command) = cli.command {
+        match cli::run(command, &databases[0].path, &save_options) {
+            Ok(()) => process::exit(0),
+            Err(error) => {
+                eprintln!("error: {}", error);
+                process::exit(error.exit_code());
+            }
+        }
+    }
 
-    let mut db = create_database(file_contents);
+    // INITIALIZE THE DATABASES
+    let mut apps = Vec::new();
+    for database in databases {
+        let invalid_data = |error| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", database.path.display(), error),
+            )
+        };
+        let storage = open_storage(&database.path, save_options).map_err(invalid_data)?;
+        let db = storage.load().map_err(invalid_data)?;
+        apps.push(App {
+            name: database.name,
+            data_base: db,
+            storage,
+            save_options,
+            ..App::default()
+        });
+    }
+    let mut workspace = Workspace { apps, active: 0 };
 
     // TUI INITIALIZATION
     enable_raw_mode()?;
@@ -38,10 +74,8 @@ fn main() -> Result<(), std::io::Error> {
     execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
-    let mut app: App = App::default();
-    app.data_base = db;
 
-    run_app(&mut terminal, &mut app)?;
+    run_app(&mut terminal, &mut workspace)?;
 
     disable_raw_mode()?;
     execute!(
@@ -51,19 +85,11 @@ fn main() -> Result<(), std::io::Error> {
     )?;
     terminal.show_cursor()?;
 
-    let mut file = File::create("./baza_de_date.io").expect("unable to open file");
-
-    for (_, client) in app.data_base.clienti.iter().enumerate() {
-        let mut data = String::new();
-        data.push_str(client.prenume.as_str());
-        data.push('\n');
-        data.push_str(client.nume.as_str());
-        data.push('\n');
-        data.push_str(client.nr_telefon.as_str());
-        data.push('\n');
-        data.push_str(client.adresa.as_str());
-        data.push('\n');
-        file.write_all(data.as_bytes()).expect("Unable to write");
+    for app in workspace.apps.iter_mut() {
+        app.save().map_err(io::Error::other)?;
+        if let Some(journal) = &app.data_base.journal {
+            journal.compact()?;
+        }
     }
 
     Ok(())
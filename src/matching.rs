@@ -0,0 +1,195 @@
+use std::ops::Range;
+
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+/// Points for each character of the pattern found in the text.
+const MATCH: i32 = 16;
+/// Extra points for a character right after the previous one matched.
+const CONSECUTIVE: i32 = 12;
+/// Extra points for a character starting a word.
+const WORD_START: i32 = 10;
+/// Most points lost for the characters before the first match.
+const MAX_LEADING: usize = 8;
+
+/// Text folded for matching: decomposed, stripped of accents and in
+/// lowercase, so `Ștefan`, `Ştefan` and `STEFAN` all read `stefan`.
+///
+/// Each folded character remembers the character of the original text it
+/// comes from, so matches can be highlighted there.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Folded {
+    chars: Vec<char>,
+    origins: Vec<usize>,
+}
+
+impl Folded {
+    pub fn new(text: &str) -> Folded {
+        let mut folded = Folded {
+            chars: Vec::with_capacity(text.len()),
+            origins: Vec::with_capacity(text.len()),
+        };
+        for (index, c) in text.chars().enumerate() {
+            for c in c.nfd().filter(|c| !is_combining_mark(*c)) {
+                for c in c.to_lowercase() {
+                    folded.chars.push(c);
+                    folded.origins.push(index);
+                }
+            }
+        }
+        folded
+    }
+
+    pub fn chars(&self) -> &[char] {
+        &self.chars
+    }
+
+    /// Indices of the original characters the folded ones in `range` come
+    /// from, in order, each possibly repeated.
+    pub fn origins(&self, range: Range<usize>) -> &[usize] {
+        &self.origins[range]
+    }
+
+    /// Start of every place `needle` is found, overlapping ones included.
+    pub fn find_all<'a>(&'a self, needle: &'a [char]) -> impl Iterator<Item = usize> + 'a {
+        self.chars
+            .windows(needle.len().max(1))
+            .enumerate()
+            .filter(move |(_, window)| *window == needle)
+            .map(|(start, _)| start)
+    }
+}
+
+/// Folds text the way `Folded` does, keeping only the characters.
+pub fn fold(text: &str) -> Vec<char> {
+    Folded::new(text).chars
+}
+
+/// How well a fuzzy pattern matches a text.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FuzzyMatch {
+    /// Higher for closer matches.
+    pub score: i32,
+    /// Indices of the original characters matched.
+    pub positions: Vec<usize>,
+}
+
+/// Matches a folded pattern against a text when its characters appear in
+/// it in order, not necessarily next to each other.
+///
+/// The shortest stretch of text holding the pattern is scored: every
+/// character matched earns points, more when it follows the previous one or
+/// starts a word, and every character skipped in between or before costs one.
+pub fn fuzzy_match(pattern: &[char], text: &Folded) -> Option<FuzzyMatch> {
+    let chars = &text.chars;
+    let Some(last) = pattern.len().checked_sub(1) else {
+        return Some(FuzzyMatch {
+            score: 0,
+            positions: Vec::new(),
+        });
+    };
+
+    // Every stretch found starts after the previous one, so a shorter one
+    // later in the text is never missed.
+    let mut found = stretch(pattern, chars, 0)?;
+    let mut from = found[0] + 1;
+    while let Some(next) = stretch(pattern, chars, from) {
+        from = next[0] + 1;
+        if next[last] - next[0] < found[last] - found[0] {
+            found = next;
+        }
+    }
+
+    let mut score = -(found[0].min(MAX_LEADING) as i32);
+    for (n, &index) in found.iter().enumerate() {
+        score += MATCH;
+        if index == 0 || !chars[index - 1].is_alphanumeric() {
+            score += WORD_START;
+        }
+        if n > 0 {
+            match index - found[n - 1] - 1 {
+                0 => score += CONSECUTIVE,
+                gap => score -= gap as i32,
+            }
+        }
+    }
+
+    let mut positions: Vec<usize> = found.iter().map(|&index| text.origins[index]).collect();
+    positions.dedup();
+    Some(FuzzyMatch { score, positions })
+}
+
+/// Indices of the characters of the first stretch of `chars` holding the
+/// pattern from `from` on, made as short as it can be from its end.
+fn stretch(pattern: &[char], chars: &[char], from: usize) -> Option<Vec<usize>> {
+    let last = pattern.len() - 1;
+
+    // The earliest place the whole pattern is found ends the stretch...
+    let mut next = 0;
+    let end = from
+        + chars[from..].iter().position(|c| {
+            if *c == pattern[next] {
+                next += 1;
+            }
+            next > last
+        })?;
+
+    // ...and matching backwards from there finds where it starts.
+    let mut found = Vec::with_capacity(pattern.len());
+    let mut next = last;
+    for index in (from..=end).rev() {
+        if chars[index] == pattern[next] {
+            found.push(index);
+            if next == 0 {
+                break;
+            }
+            next -= 1;
+        }
+    }
+    found.reverse();
+    Some(found)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn folding_ignores_accents_and_case() {
+        assert_eq!(fold("Ștefan Țăran"), fold("stefan taran"));
+        assert_eq!(fold("Şerban ÎNGER"), fold("serban inger"));
+        // Already decomposed: `a` followed by a combining breve.
+        assert_eq!(fold("Ta\u{306}nase"), fold("tanase"));
+
+        let folded = Folded::new("Ștefănescu");
+        let needle = fold("nesc");
+        let starts: Vec<usize> = folded.find_all(&needle).collect();
+        assert_eq!(starts, [5]);
+        assert_eq!(folded.origins(5..9), [5, 6, 7, 8]);
+    }
+
+    #[test]
+    fn closer_fuzzy_matches_score_higher() {
+        let score = |pattern: &str, text: &str| {
+            fuzzy_match(&fold(pattern), &Folded::new(text)).map(|found| found.score)
+        };
+        assert_eq!(score("xyz", "Stanciu"), None);
+        assert!(score("stan", "Stanciu") > score("stan", "Sebastian"));
+        assert!(score("ion", "Ionescu") > score("ion", "Simion"));
+        assert!(score("ion", "Simion") > score("ion", "Popovici Ioana"));
+
+        let found = fuzzy_match(&fold("stf"), &Folded::new("Ștefan")).unwrap();
+        assert_eq!(found.positions, [0, 1, 3]);
+    }
+
+    #[test]
+    fn the_shortest_stretch_is_scored() {
+        let text = Folded::new("a_b_c_abc");
+        let found = fuzzy_match(&fold("abc"), &text).unwrap();
+        assert_eq!(found.positions, [6, 7, 8]);
+        // Scored as if the first, longer stretch were not there.
+        assert_eq!(
+            found,
+            fuzzy_match(&fold("abc"), &Folded::new("xxxxx_abc")).unwrap()
+        );
+    }
+}
@@ -0,0 +1,720 @@
+use std::{cmp::Reverse, marker::PhantomData, ops::Range};
+
+use regex::Regex;
+use thiserror::Error;
+
+use crate::matching::{fold, fuzzy_match, Folded, FuzzyMatch};
+use crate::schema::Record;
+
+/// How the text typed in the search bar is read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SearchMode {
+    /// As a query, described on `Query`.
+    #[default]
+    Query,
+    /// As a fuzzy pattern, whose characters must appear in the field in
+    /// order, the closest matches coming first.
+    Fuzzy,
+    /// As a regular expression, which finds the values it matches part of.
+    Regex,
+}
+
+impl SearchMode {
+    /// The mode after this one, for cycling through them.
+    pub fn next(self) -> SearchMode {
+        match self {
+            SearchMode::Query => SearchMode::Fuzzy,
+            SearchMode::Fuzzy => SearchMode::Regex,
+            SearchMode::Regex => SearchMode::Query,
+        }
+    }
+
+    /// How the search bar title names the mode.
+    pub fn title(self) -> &'static str {
+        match self {
+            SearchMode::Query => "Search",
+            SearchMode::Fuzzy => "Fuzzy search",
+            SearchMode::Regex => "Regex search",
+        }
+    }
+}
+
+/// A search written in the query language of the search bar.
+///
+/// A term is a word or a quoted phrase, optionally preceded by a field and
+/// a colon, like `last:Stanciu` or `address:"Str. Lunga"`. Terms without a
+/// field search the default column, or every column if there is none. A
+/// value starting with `=` must match the whole field, and one starting with
+/// `^` must start it; otherwise the field only has to contain it. Case and
+/// accents are ignored, so `stefan` finds `Ștefan`.
+///
+/// Terms combine with `AND`, `OR`, `NOT` and parentheses, `NOT` binding
+/// tightest and `OR` loosest. Terms next to each other must all match, as if
+/// joined by `AND`: `last:Stanciu AND NOT phone:^003`.
+#[derive(Debug, Clone)]
+pub struct Query<R> {
+    search: Search,
+    record: PhantomData<fn(&R) -> bool>,
+}
+
+/// The empty query, matching every record.
+impl<R> Default for Query<R> {
+    fn default() -> Query<R> {
+        Query {
+            search: Search::All,
+            record: PhantomData,
+        }
+    }
+}
+
+/// What a query looks for.
+#[derive(Debug, Clone)]
+enum Search {
+    All,
+    Expr(Expr),
+    Fuzzy {
+        column: Option<usize>,
+        /// Folded, without whitespace.
+        pattern: Vec<char>,
+    },
+    Regex {
+        column: Option<usize>,
+        regex: Regex,
+    },
+}
+
+/// A node of a parsed query.
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Term(Term),
+}
+
+/// Compares one field with a value.
+#[derive(Debug, Clone, PartialEq)]
+struct Term {
+    field: Field,
+    kind: MatchKind,
+    /// Folded, like the field values it is compared with.
+    value: Vec<char>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Field {
+    Id,
+    Column(usize),
+    /// Every column.
+    Any,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum MatchKind {
+    Contains,
+    Equals,
+    Prefix,
+}
+
+/// Why a query could not be parsed, and where.
+#[derive(Debug, Clone, PartialEq, Error)]
+#[error("{message}")]
+pub struct ParseError {
+    pub message: String,
+    /// Byte range of the query the error is about.
+    pub span: Range<usize>,
+}
+
+impl ParseError {
+    fn new(message: impl Into<String>, span: Range<usize>) -> ParseError {
+        ParseError {
+            message: message.into(),
+            span,
+        }
+    }
+}
+
+/// A token of the query, with its byte range.
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    And,
+    Or,
+    Not,
+    Term {
+        field: Option<String>,
+        kind: MatchKind,
+        value: Vec<char>,
+    },
+}
+
+impl<R: Record> Query<R> {
+    /// Reads the text typed in the search bar the way `mode` asks for,
+    /// searching `column`, or every column if `None`, unless it says
+    /// otherwise.
+    pub fn new(
+        mode: SearchMode,
+        input: &str,
+        column: Option<usize>,
+    ) -> Result<Query<R>, ParseError> {
+        match mode {
+            SearchMode::Query => Query::parse(input, column),
+            SearchMode::Fuzzy => Ok(Query::fuzzy(input, column)),
+            SearchMode::Regex => Query::regex(input, column),
+        }
+    }
+
+    /// Parses a query whose terms without a field search `default_column`,
+    /// or every column if `None`.
+    pub fn parse(input: &str, default_column: Option<usize>) -> Result<Query<R>, ParseError> {
+        let tokens = tokenize(input)?;
+        let mut parser = Parser {
+            tokens: &tokens,
+            position: 0,
+            end: input.len(),
+            default_field: default_column.map_or(Field::Any, Field::Column),
+            record: PhantomData::<R>,
+        };
+        let search = match tokens.is_empty() {
+            true => Search::All,
+            false => Search::Expr(parser.or()?),
+        };
+        if let Some((_, span)) = parser.tokens.get(parser.position) {
+            return Err(ParseError::new("unexpected `)`", span.clone()));
+        }
+        Ok(Query {
+            search,
+            record: PhantomData,
+        })
+    }
+
+    /// A fuzzy search of `column`, or of every column if `None`, in which
+    /// any text is valid.
+    pub fn fuzzy(input: &str, column: Option<usize>) -> Query<R> {
+        let pattern: Vec<char> = fold(input)
+            .into_iter()
+            .filter(|c| !c.is_whitespace())
+            .collect();
+        let search = match pattern.is_empty() {
+            true => Search::All,
+            false => Search::Fuzzy { column, pattern },
+        };
+        Query {
+            search,
+            record: PhantomData,
+        }
+    }
+
+    /// A search of `column`, or of every column if `None`, for the values a
+    /// regular expression matches part of. Case matters, unless the
+    /// expression starts with `(?i)`.
+    pub fn regex(input: &str, column: Option<usize>) -> Result<Query<R>, ParseError> {
+        let search = match input.is_empty() {
+            true => Search::All,
+            false => Search::Regex {
+                column,
+                regex: Regex::new(input).map_err(|error| regex_error(input, error))?,
+            },
+        };
+        Ok(Query {
+            search,
+            record: PhantomData,
+        })
+    }
+
+    /// Whether the query is empty, matching every record rather than
+    /// looking for anything.
+    pub fn is_empty(&self) -> bool {
+        matches!(self.search, Search::All)
+    }
+
+    /// How closely the record matches the query, higher being closer, or
+    /// `None` if it does not. Only fuzzy searches tell matches apart.
+    pub fn score(&self, record: &R) -> Option<i32> {
+        match &self.search {
+            Search::All => Some(0),
+            Search::Expr(expr) => evaluate(expr, record).then_some(0),
+            Search::Fuzzy { column, pattern } => {
+                best_fuzzy_match(record, *column, pattern).map(|(_, found)| found.score)
+            }
+            Search::Regex { column, regex } => columns::<R>(*column)
+                .any(|index| regex.is_match(&(R::SCHEMA.columns[index].get)(record)))
+                .then_some(0),
+        }
+    }
+
+    /// The characters of each column of a matching record that the query
+    /// matched, by index, for highlighting. Terms under `NOT` match none,
+    /// and a fuzzy search only matches its closest column.
+    pub fn highlights(&self, record: &R) -> Vec<Vec<usize>> {
+        let mut highlights = vec![Vec::new(); R::SCHEMA.columns.len()];
+        match &self.search {
+            Search::All => {}
+            Search::Expr(expr) => {
+                let mut terms = Vec::new();
+                positive_terms(expr, &mut terms);
+                for term in terms {
+                    for index in term.field.columns::<R>() {
+                        let value = Folded::new(&(R::SCHEMA.columns[index].get)(record));
+                        highlights[index].extend(term_matches(term, &value));
+                    }
+                }
+            }
+            Search::Fuzzy { column, pattern } => {
+                if let Some((index, found)) = best_fuzzy_match(record, *column, pattern) {
+                    highlights[index] = found.positions;
+                }
+            }
+            Search::Regex { column, regex } => {
+                for index in columns::<R>(*column) {
+                    let value = (R::SCHEMA.columns[index].get)(record);
+                    let found: Vec<Range<usize>> =
+                        regex.find_iter(&value).map(|found| found.range()).collect();
+                    highlights[index] = value
+                        .char_indices()
+                        .enumerate()
+                        .filter(|(_, (byte, _))| found.iter().any(|range| range.contains(byte)))
+                        .map(|(index, _)| index)
+                        .collect();
+                }
+            }
+        }
+        for column in &mut highlights {
+            column.sort_unstable();
+            column.dedup();
+        }
+        highlights
+    }
+}
+
+impl Field {
+    /// The indices of the columns of `R` the field stands for.
+    fn columns<R: Record>(self) -> Range<usize> {
+        match self {
+            Field::Id => 0..0,
+            Field::Column(index) => columns::<R>(Some(index)),
+            Field::Any => columns::<R>(None),
+        }
+    }
+}
+
+/// The indices of `column`, or of every column of `R` if `None`.
+fn columns<R: Record>(column: Option<usize>) -> Range<usize> {
+    match column {
+        Some(index) => index..index + 1,
+        None => 0..R::SCHEMA.columns.len(),
+    }
+}
+
+/// The column among those searched that the pattern matches best, and how.
+fn best_fuzzy_match<R: Record>(
+    record: &R,
+    column: Option<usize>,
+    pattern: &[char],
+) -> Option<(usize, FuzzyMatch)> {
+    columns::<R>(column)
+        .filter_map(|index| {
+            let value = Folded::new(&(R::SCHEMA.columns[index].get)(record));
+            Some((index, fuzzy_match(pattern, &value)?))
+        })
+        .max_by_key(|(index, found)| (found.score, Reverse(*index)))
+}
+
+/// Turns a regular expression that does not compile into an error pointing
+/// to the part of it at fault, when the regex crate tells which.
+fn regex_error(input: &str, error: regex::Error) -> ParseError {
+    let regex::Error::Syntax(text) = &error else {
+        return ParseError::new(error.to_string(), 0..input.len());
+    };
+    let message = text
+        .lines()
+        .last()
+        .and_then(|line| line.strip_prefix("error: "))
+        .unwrap_or(text);
+    // The line above the message marks the part at fault with carets, under
+    // the expression indented by four spaces.
+    let span = text.lines().rev().nth(1).and_then(|marks| {
+        let start = marks.find('^')?.checked_sub(4)?;
+        let end = marks.rfind('^')? - 4 + 1;
+        let byte = |column| {
+            input
+                .char_indices()
+                .nth(column)
+                .map_or(input.len(), |(byte, _)| byte)
+        };
+        Some(byte(start)..byte(end))
+    });
+    ParseError::new(message, span.unwrap_or(0..input.len()))
+}
+
+/// Gathers the terms a record must match for the expression to, that is
+/// those not under a `NOT`.
+fn positive_terms<'a>(expr: &'a Expr, terms: &mut Vec<&'a Term>) {
+    match expr {
+        Expr::And(left, right) | Expr::Or(left, right) => {
+            positive_terms(left, terms);
+            positive_terms(right, terms);
+        }
+        Expr::Not(_) => {}
+        Expr::Term(term) => terms.push(term),
+    }
+}
+
+/// Indices of the original characters of `value` the term matches.
+fn term_matches(term: &Term, value: &Folded) -> Vec<usize> {
+    let length = term.value.len();
+    let chars = value.chars();
+    match term.kind {
+        MatchKind::Contains => value
+            .find_all(&term.value)
+            .flat_map(|start| value.origins(start..start + length).to_vec())
+            .collect(),
+        MatchKind::Prefix if chars.starts_with(&term.value) => value.origins(0..length).to_vec(),
+        MatchKind::Equals if chars == term.value => value.origins(0..length).to_vec(),
+        MatchKind::Prefix | MatchKind::Equals => Vec::new(),
+    }
+}
+
+/// Whether the folded value matches the term.
+fn term_matches_value(term: &Term, value: &[char]) -> bool {
+    match term.kind {
+        MatchKind::Contains => {
+            term.value.is_empty()
+                || value
+                    .windows(term.value.len())
+                    .any(|window| window == term.value)
+        }
+        MatchKind::Equals => value == term.value,
+        MatchKind::Prefix => value.starts_with(&term.value),
+    }
+}
+
+fn evaluate<R: Record>(expr: &Expr, record: &R) -> bool {
+    match expr {
+        Expr::And(left, right) => evaluate(left, record) && evaluate(right, record),
+        Expr::Or(left, right) => evaluate(left, record) || evaluate(right, record),
+        Expr::Not(expr) => !evaluate(expr, record),
+        Expr::Term(term) => match term.field {
+            Field::Id => term_matches_value(term, &fold(&record.id().to_string())),
+            field => field.columns::<R>().any(|index| {
+                term_matches_value(term, &fold(&(R::SCHEMA.columns[index].get)(record)))
+            }),
+        },
+    }
+}
+
+/// Splits a query into tokens.
+fn tokenize(input: &str) -> Result<Vec<(Token, Range<usize>)>, ParseError> {
+    let mut tokens = Vec::new();
+    let mut chars = input.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' | ')' => {
+                chars.next();
+                let token = if c == '(' { Token::Open } else { Token::Close };
+                tokens.push((token, start..start + 1));
+            }
+            _ => {
+                // A field name runs up to its colon; without one, the word
+                // read so far is the start of a value.
+                let mut word = String::new();
+                let mut field = None;
+                while let Some(&(_, c)) = chars.peek() {
+                    if c.is_alphanumeric() || c == '_' || c == '-' {
+                        word.push(c);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                if !word.is_empty() && chars.peek().is_some_and(|&(_, c)| c == ':') {
+                    chars.next();
+                    field = Some(std::mem::take(&mut word));
+                }
+
+                let kind = match chars.peek() {
+                    Some(&(_, '=')) if word.is_empty() => MatchKind::Equals,
+                    Some(&(_, '^')) if word.is_empty() => MatchKind::Prefix,
+                    _ => MatchKind::Contains,
+                };
+                if kind != MatchKind::Contains {
+                    chars.next();
+                }
+
+                let quoted = word.is_empty() && chars.peek().is_some_and(|&(_, c)| c == '"');
+                if quoted {
+                    let (quote, _) = chars.next().unwrap_or_default();
+                    loop {
+                        match chars.next() {
+                            Some((_, '"')) => break,
+                            Some((_, c)) => word.push(c),
+                            None => {
+                                return Err(ParseError::new("unclosed quote", quote..input.len()))
+                            }
+                        }
+                    }
+                } else {
+                    while let Some(&(_, c)) = chars.peek() {
+                        if c.is_whitespace() || c == '(' || c == ')' {
+                            break;
+                        }
+                        word.push(c);
+                        chars.next();
+                    }
+                }
+
+                let end = chars.peek().map_or(input.len(), |&(index, _)| index);
+                let keyword = field.is_none() && kind == MatchKind::Contains && !quoted;
+                let token = match (&field, word.as_str()) {
+                    (None, "AND") if keyword => Token::And,
+                    (None, "OR") if keyword => Token::Or,
+                    (None, "NOT") if keyword => Token::Not,
+                    (Some(field), "") => {
+                        return Err(ParseError::new(
+                            format!("missing value after `{}:`", field),
+                            start..end,
+                        ))
+                    }
+                    _ => Token::Term {
+                        field,
+                        kind,
+                        value: fold(&word),
+                    },
+                };
+                tokens.push((token, start..end));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent over the tokens, one method per precedence level.
+struct Parser<'a, R> {
+    tokens: &'a [(Token, Range<usize>)],
+    position: usize,
+    /// Length of the query, where errors about a missing term point.
+    end: usize,
+    /// The field searched by terms that name none.
+    default_field: Field,
+    record: PhantomData<R>,
+}
+
+impl<R: Record> Parser<'_, R> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position).map(|(token, _)| token)
+    }
+
+    /// The span of the current token, or the end of the query.
+    fn span(&self) -> Range<usize> {
+        match self.tokens.get(self.position) {
+            Some((_, span)) => span.clone(),
+            None => self.end..self.end,
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.position += 1;
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.not()?;
+        loop {
+            match self.peek() {
+                Some(Token::And) => self.position += 1,
+                Some(Token::Open | Token::Not | Token::Term { .. }) => {}
+                _ => return Ok(expr),
+            }
+            expr = Expr::And(Box::new(expr), Box::new(self.not()?));
+        }
+    }
+
+    fn not(&mut self) -> Result<Expr, ParseError> {
+        if self.peek() == Some(&Token::Not) {
+            self.position += 1;
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr, ParseError> {
+        let span = self.span();
+        let token = match self.tokens.get(self.position) {
+            Some((token, _)) => token.clone(),
+            None => {
+                let previous = self
+                    .position
+                    .checked_sub(1)
+                    .map(|index| &self.tokens[index]);
+                return Err(match previous {
+                    Some((_, span)) => ParseError::new("expected a term after this", span.clone()),
+                    None => ParseError::new("expected a term", span),
+                });
+            }
+        };
+        self.position += 1;
+        match token {
+            Token::Open => {
+                let expr = self.or()?;
+                match self.peek() {
+                    Some(Token::Close) => {
+                        self.position += 1;
+                        Ok(expr)
+                    }
+                    _ => Err(ParseError::new("unclosed `(`", span)),
+                }
+            }
+            Token::Term { field, kind, value } => {
+                let field = match field {
+                    None => self.default_field,
+                    Some(name) if name == "id" || name == R::SCHEMA.id => Field::Id,
+                    Some(name) => match R::SCHEMA.column(&name) {
+                        Some(index) => Field::Column(index),
+                        None => {
+                            return Err(ParseError::new(
+                                format!("unknown field `{}`", name),
+                                span.start..span.start + name.len(),
+                            ))
+                        }
+                    },
+                };
+                Ok(Expr::Term(Term { field, kind, value }))
+            }
+            Token::Close | Token::And | Token::Or | Token::Not => {
+                Err(ParseError::new("expected a term", span))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Client;
+
+    fn client(prenume: &str, nume: &str, nr_telefon: &str) -> Client {
+        Client {
+            prenume: prenume.to_string(),
+            nume: nume.to_string(),
+            nr_telefon: nr_telefon.to_string(),
+            ..Client::new()
+        }
+    }
+
+    #[test]
+    fn evaluates_fields_operators_and_phrases() {
+        let clients = [
+            client("Bogdan", "Stanciu", "001112324152"),
+            client("Octavian", "Stanciu", "+40722123456"),
+            client("Ana Maria", "Pop", "0722123456"),
+        ];
+        let matching = |query: &str| -> Vec<&str> {
+            let query = Query::<Client>::parse(query, Some(0)).unwrap();
+            clients
+                .iter()
+                .filter(|client| query.score(client).is_some())
+                .map(|client| client.prenume.as_str())
+                .collect()
+        };
+
+        assert_eq!(matching(""), ["Bogdan", "Octavian", "Ana Maria"]);
+        assert_eq!(
+            matching("last:Stanciu AND NOT phone:^003"),
+            ["Bogdan", "Octavian"]
+        );
+        assert_eq!(matching("last:stanciu NOT phone:^001"), ["Octavian"]);
+        assert_eq!(
+            matching("\"ana m\" OR last:=stanciu oct"),
+            ["Octavian", "Ana Maria"]
+        );
+        assert_eq!(
+            matching("(last:pop OR first:bog) phone:0722"),
+            ["Ana Maria"]
+        );
+        assert_eq!(matching("first:=ana"), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn accents_are_ignored_and_matches_highlighted() {
+        let client = client("Ștefan", "Țăranu", "0722123456");
+        let query = Query::<Client>::parse("STEFAN last:^tar NOT phone:0722", Some(0)).unwrap();
+        assert_eq!(query.score(&client), None);
+
+        let query = Query::<Client>::parse("stefan last:^tar", Some(0)).unwrap();
+        let highlights = query.highlights(&client);
+        assert_eq!(highlights[0], [0, 1, 2, 3, 4, 5]);
+        assert_eq!(highlights[1], [0, 1, 2]);
+
+        let fuzzy = Query::<Client>::fuzzy("șfn", Some(0));
+        assert!(fuzzy.score(&client).is_some());
+        assert_eq!(fuzzy.highlights(&client)[0], [0, 3, 5]);
+    }
+
+    #[test]
+    fn terms_without_a_field_search_every_column() {
+        let client = client("Ștefan", "Țăranu", "0722123456");
+        let any = Query::<Client>::parse("an", None).unwrap();
+        let highlights = any.highlights(&client);
+        assert_eq!((&highlights[0], &highlights[1]), (&vec![4, 5], &vec![3, 4]));
+        assert!(highlights[2].is_empty());
+        assert!(any.score(&client).is_some());
+        assert!(Query::<Client>::parse("an", Some(2))
+            .unwrap()
+            .score(&client)
+            .is_none());
+
+        let any = Query::<Client>::fuzzy("tru", None);
+        assert!(any.highlights(&client)[0].is_empty());
+        assert_eq!(any.highlights(&client)[1], [0, 2, 5]);
+    }
+
+    #[test]
+    fn errors_point_to_the_offending_text() {
+        let error = |query: &str| Query::<Client>::parse(query, Some(0)).unwrap_err();
+        assert_eq!(
+            error("last:Pop AND email:x"),
+            ParseError::new("unknown field `email`", 13..18)
+        );
+        assert_eq!(error("(last:Pop"), ParseError::new("unclosed `(`", 0..1));
+        assert_eq!(error("last:Pop)"), ParseError::new("unexpected `)`", 8..9));
+        assert_eq!(
+            error("\"Str. Lunga"),
+            ParseError::new("unclosed quote", 0..11)
+        );
+        assert_eq!(
+            error("Pop OR"),
+            ParseError::new("expected a term after this", 4..6)
+        );
+        assert_eq!(error("last: Pop").message, "missing value after `last:`");
+    }
+
+    #[test]
+    fn regexes_match_highlight_and_point_to_errors() {
+        let client = client("Ștefan", "Țăranu", "0722123456");
+        let regex = Query::<Client>::regex("ăr|u$", Some(1)).unwrap();
+        assert!(regex.score(&client).is_some());
+        assert_eq!(regex.highlights(&client)[1], [1, 2, 5]);
+        assert!(Query::<Client>::regex("^07", Some(0))
+            .unwrap()
+            .score(&client)
+            .is_none());
+
+        let error = |regex: &str| Query::<Client>::regex(regex, None).unwrap_err();
+        assert_eq!(error("(07"), ParseError::new("unclosed group", 0..1));
+        assert_eq!(
+            error("ă{3,1}"),
+            ParseError::new(
+                "invalid repetition count range, the start must be <= the end",
+                2..7
+            )
+        );
+    }
+}
@@ -0,0 +1,350 @@
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    sync::{Mutex, OnceLock},
+};
+
+use regex::Regex;
+
+use crate::types::SortOrd;
+
+/// The kind of value a column holds, which decides how it is checked and sorted.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ColumnType {
+    Text,
+    /// Digits, with an optional `+` and separators, stored without the
+    /// separators and with a `00` prefix written as `+`.
+    Phone,
+    Integer,
+}
+
+/// Fewest and most digits accepted in a phone number.
+const PHONE_DIGITS: (usize, usize) = (6, 15);
+
+/// A check made on the value of a column, on top of its type.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Rule {
+    /// The value has between `min` and `max` characters.
+    Length { min: usize, max: usize },
+    /// The whole value matches `pattern`; `message` follows the column
+    /// title when it does not.
+    Pattern {
+        pattern: &'static str,
+        message: &'static str,
+    },
+}
+
+/// A column of a table: how it is named, checked and read from a record.
+pub struct Column<R> {
+    /// Name used in files, in SQL and on the command line.
+    pub name: &'static str,
+    /// Name shown in the interface.
+    pub title: &'static str,
+    /// Other names accepted in imported headers and on the command line.
+    pub aliases: &'static [&'static str],
+    pub kind: ColumnType,
+    pub required: bool,
+    /// Checked in order on non-empty values.
+    pub rules: &'static [Rule],
+    /// Minimum width of the column when the table is displayed.
+    pub width: u16,
+    /// The table whose id this column holds, for foreign keys.
+    pub references: Option<&'static str>,
+    pub get: fn(&R) -> String,
+    /// Stores a value returned by `TableSchema::check_value`.
+    pub set: fn(&mut R, String),
+}
+
+/// The columns of a table, in the order they are shown and written.
+///
+/// The id is not a column: every record has one, and it is handled by
+/// the table itself.
+pub struct TableSchema<R: 'static> {
+    pub name: &'static str,
+    /// Name of the id, such as `nr_ordine`.
+    pub id: &'static str,
+    pub columns: &'static [Column<R>],
+}
+
+/// A record of a table described by a schema.
+pub trait Record: Clone + Default + 'static {
+    const SCHEMA: TableSchema<Self>;
+
+    fn id(&self) -> i32;
+
+    fn set_id(&mut self, id: i32);
+}
+
+/// How the records of a table are sorted.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SortMode {
+    Id,
+    /// By the column with the given index.
+    Column(usize),
+}
+
+/// One key of a sort spec, sorting by a field in either order.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SortKey {
+    pub mode: SortMode,
+    pub order: SortOrd,
+}
+
+impl SortKey {
+    pub fn new(mode: SortMode, order: SortOrd) -> SortKey {
+        SortKey { mode, order }
+    }
+
+    /// The same key, sorting the other way.
+    pub fn reversed(self) -> SortKey {
+        let order = match self.order {
+            SortOrd::Incr => SortOrd::Decr,
+            SortOrd::Decr => SortOrd::Incr,
+        };
+        SortKey { order, ..self }
+    }
+}
+
+impl<R: Record> TableSchema<R> {
+    /// The index of the column with the given name or alias, ignoring
+    /// case and treating spaces and dashes as underscores.
+    pub fn column(&self, name: &str) -> Option<usize> {
+        let name = name.trim().to_lowercase().replace([' ', '-'], "_");
+        self.columns
+            .iter()
+            .position(|column| column.name == name || column.aliases.contains(&name.as_str()))
+    }
+
+    /// The names of every column, for messages listing them.
+    pub fn column_names(&self) -> String {
+        let names: Vec<&str> = self.columns.iter().map(|column| column.name).collect();
+        names.join(", ")
+    }
+
+    /// Checks a trimmed value against the type, required flag and rules of
+    /// a column, returning it the way it is stored.
+    pub fn check_value(&self, column: usize, value: &str) -> Result<String, String> {
+        let column = &self.columns[column];
+        if value.is_empty() {
+            return match column.required {
+                true => Err(format!("{} is required", column.title)),
+                false => Ok(String::new()),
+            };
+        }
+        let value = match column.kind {
+            ColumnType::Text => value.to_string(),
+            ColumnType::Phone => {
+                normalize_phone(value).map_err(|message| format!("{} {}", column.title, message))?
+            }
+            ColumnType::Integer => match value.parse::<i32>() {
+                Ok(_) => value.to_string(),
+                Err(_) => return Err(format!("{} must be a whole number", column.title)),
+            },
+        };
+        for rule in column.rules {
+            match *rule {
+                Rule::Length { min, max } => {
+                    let length = value.chars().count();
+                    if length < min || length > max {
+                        return Err(format!(
+                            "{} must have between {} and {} characters",
+                            column.title, min, max
+                        ));
+                    }
+                }
+                Rule::Pattern { pattern, message } => {
+                    if !compiled(pattern).is_match(&value) {
+                        return Err(format!("{} {}", column.title, message));
+                    }
+                }
+            }
+        }
+        Ok(value)
+    }
+
+    /// Checks the values, one per column, and builds the record they
+    /// describe, or returns the error of every rejected column.
+    pub fn validate(&self, values: &[String]) -> Result<R, Vec<Option<String>>> {
+        let mut record = R::default();
+        let mut errors = vec![None; self.columns.len()];
+        for (index, column) in self.columns.iter().enumerate() {
+            let value = values.get(index).map_or("", |value| value.trim());
+            match self.check_value(index, value) {
+                Ok(value) => (column.set)(&mut record, value),
+                Err(error) => errors[index] = Some(error),
+            }
+        }
+        match errors.iter().any(Option::is_some) {
+            true => Err(errors),
+            false => Ok(record),
+        }
+    }
+
+    /// Checks the values, one per column, and builds the record they
+    /// describe, or returns the error of the first rejected column.
+    pub fn build(&self, values: &[String]) -> Result<R, String> {
+        self.validate(values)
+            .map_err(|errors| errors.into_iter().flatten().next().unwrap_or_default())
+    }
+
+    /// Checks a record built elsewhere, returning it with its values trimmed.
+    pub fn check(&self, record: &R) -> Result<R, String> {
+        let mut checked = self.build(&self.values(record))?;
+        checked.set_id(record.id());
+        Ok(checked)
+    }
+
+    /// The values of a record, one per column.
+    pub fn values(&self, record: &R) -> Vec<String> {
+        self.columns
+            .iter()
+            .map(|column| (column.get)(record))
+            .collect()
+    }
+
+    /// The id of a record followed by its values, as a table row.
+    pub fn row(&self, record: &R) -> Vec<String> {
+        let mut row = vec![record.id().to_string()];
+        row.extend(self.values(record));
+        row
+    }
+
+    /// Compares two records by each key in turn, the first key telling
+    /// them apart deciding. Records alike for every key come by id, so the
+    /// order never depends on where the records are stored.
+    pub fn compare_by(&self, keys: &[SortKey], a: &R, b: &R) -> Ordering {
+        keys.iter()
+            .map(|key| match key.order {
+                SortOrd::Incr => self.compare(key.mode, a, b),
+                SortOrd::Decr => self.compare(key.mode, b, a),
+            })
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or_else(|| a.id().cmp(&b.id()))
+    }
+
+    /// Compares two records the way the sort mode asks for.
+    pub fn compare(&self, sort_mode: SortMode, a: &R, b: &R) -> Ordering {
+        match sort_mode {
+            SortMode::Id => a.id().cmp(&b.id()),
+            SortMode::Column(index) => {
+                let column = &self.columns[index];
+                let (a, b) = ((column.get)(a), (column.get)(b));
+                match column.kind {
+                    ColumnType::Integer => {
+                        let number = |value: &str| value.parse::<i32>().ok();
+                        number(&a).cmp(&number(&b))
+                    }
+                    ColumnType::Text | ColumnType::Phone => a.cmp(&b),
+                }
+            }
+        }
+    }
+}
+
+/// Removes the separators of a phone number and writes a `00` international
+/// prefix as `+`, so the same number is always stored the same way.
+fn normalize_phone(value: &str) -> Result<String, String> {
+    let mut digits: String = value
+        .chars()
+        .filter(|c| !matches!(c, ' ' | '-' | '.' | '/' | '(' | ')'))
+        .collect();
+    if let Some(rest) = digits.strip_prefix("00") {
+        digits = format!("+{}", rest);
+    }
+    let number = digits.strip_prefix('+').unwrap_or(&digits);
+    if !number.chars().all(|c| c.is_ascii_digit()) {
+        return Err(String::from("can only contain digits"));
+    }
+    let (min, max) = PHONE_DIGITS;
+    if number.len() < min || number.len() > max {
+        return Err(format!("must have between {} and {} digits", min, max));
+    }
+    Ok(digits)
+}
+
+/// The compiled form of a rule pattern, compiled the first time it is used.
+fn compiled(pattern: &'static str) -> Regex {
+    static CACHE: OnceLock<Mutex<HashMap<&'static str, Regex>>> = OnceLock::new();
+    let mut cache = CACHE
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    cache
+        .entry(pattern)
+        .or_insert_with(|| {
+            Regex::new(&format!("^(?:{})$", pattern)).expect("rule patterns are valid")
+        })
+        .clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Client;
+
+    #[test]
+    fn columns_are_found_by_alias_and_checked_in_order() {
+        let schema = &Client::SCHEMA;
+        assert_eq!(schema.column("Last Name"), schema.column("nume"));
+        assert_eq!(schema.column("e-mail"), None);
+
+        let values = |values: [&str; 4]| values.map(String::from);
+        let client = schema
+            .build(&values([" Ion ", "Popescu", "0040 722-123 456", ""]))
+            .unwrap();
+        assert_eq!(
+            schema.row(&client),
+            ["0", "Ion", "Popescu", "+40722123456", ""]
+        );
+        assert_eq!(
+            schema.build(&values(["Ion", "", "abc", ""])),
+            Err(String::from("Last Name is required"))
+        );
+        assert_eq!(
+            schema.build(&values(["Ion", "Pop", "abc", ""])),
+            Err(String::from("Phone Number can only contain digits"))
+        );
+    }
+
+    #[test]
+    fn every_rejected_column_gets_its_own_error() {
+        let values = ["Ion2", "", "0722", "Cluj"].map(String::from);
+        let errors = Client::SCHEMA.validate(&values).unwrap_err();
+        assert_eq!(
+            errors,
+            [
+                Some(String::from(
+                    "First Name can only contain letters, spaces and . ' -"
+                )),
+                Some(String::from("Last Name is required")),
+                Some(String::from(
+                    "Phone Number must have between 6 and 15 digits"
+                )),
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn sort_keys_apply_in_turn_and_ties_go_by_id() {
+        let client = |nr_ordine, prenume: &str, nume: &str| Client {
+            nr_ordine,
+            prenume: prenume.to_string(),
+            nume: nume.to_string(),
+            ..Client::new()
+        };
+        let mut clients = [
+            client(3, "Ion", "Pop"),
+            client(0, "Ana", "Stanciu"),
+            client(2, "Ion", "Pop"),
+            client(1, "Bogdan", "Pop"),
+        ];
+        let keys = [
+            SortKey::new(SortMode::Column(1), SortOrd::Incr),
+            SortKey::new(SortMode::Column(0), SortOrd::Decr),
+        ];
+        clients.sort_by(|a, b| Client::SCHEMA.compare_by(&keys, a, b));
+        let ids: Vec<i32> = clients.iter().map(|client| client.nr_ordine).collect();
+        assert_eq!(ids, [2, 3, 1, 0]);
+    }
+}
@@ -0,0 +1,356 @@
+use std::path::{Path, PathBuf};
+
+use rusqlite::{
+    params, params_from_iter,
+    types::{Value, ValueRef},
+    Connection, OptionalExtension, Row, Transaction,
+};
+
+use crate::journal::{Journal, Mutation};
+use crate::schema::{ColumnType, Record};
+use crate::storage::{DatabaseError, Storage};
+use crate::types::{BazaDate, Client, Comanda};
+
+/// Version of the schema created by `SqliteStorage::open`.
+const SCHEMA_VERSION: i64 = 1;
+
+/// A database kept in an SQLite file, which other tools can query directly.
+///
+/// Clients and orders live in tables named and laid out after their schema,
+/// keyed by their id, and foreign keys are declared and enforced. The
+/// highest ids given and the journal sequence number are kept in the
+/// `meta` table.
+pub struct SqliteStorage {
+    path: PathBuf,
+    connection: Connection,
+}
+
+impl SqliteStorage {
+    /// Opens the file at `path`, creating it and its tables if needed.
+    pub fn open(path: &Path) -> Result<SqliteStorage, DatabaseError> {
+        let connection = Connection::open(path)?;
+        connection.execute_batch(&format!(
+            "PRAGMA foreign_keys = ON;
+             {}
+             {}
+             CREATE TABLE IF NOT EXISTS meta (
+                 key   TEXT PRIMARY KEY,
+                 value INTEGER NOT NULL
+             );",
+            create_table::<Client>(),
+            create_table::<Comanda>()
+        ))?;
+
+        let storage = SqliteStorage {
+            path: path.to_path_buf(),
+            connection,
+        };
+        match storage.meta("version")? {
+            None => storage.connection.execute(
+                "INSERT INTO meta (key, value) VALUES ('version', ?1)",
+                params![SCHEMA_VERSION],
+            )?,
+            Some(SCHEMA_VERSION) => 0,
+            Some(version) => return Err(DatabaseError::UnsupportedVersion(version as u32)),
+        };
+        Ok(storage)
+    }
+
+    /// Reads a value of the `meta` table.
+    fn meta(&self, key: &str) -> Result<Option<i64>, DatabaseError> {
+        Ok(self
+            .connection
+            .query_row("SELECT value FROM meta WHERE key = ?1", [key], |row| {
+                row.get(0)
+            })
+            .optional()?)
+    }
+
+    /// Reads the records of `SELECT <id>, <columns> FROM <table> <clauses>`,
+    /// which takes the given parameters.
+    fn query<R: Record>(
+        &self,
+        clauses: &str,
+        params: impl rusqlite::Params,
+    ) -> Result<Vec<R>, DatabaseError> {
+        let schema = &R::SCHEMA;
+        let sql = format!(
+            "SELECT {}, {} FROM {} {}",
+            schema.id,
+            schema.column_names(),
+            schema.name,
+            clauses
+        );
+        let mut statement = self.connection.prepare(&sql)?;
+        let records = statement
+            .query_map(params, read_row::<R>)?
+            .collect::<Result<Vec<R>, rusqlite::Error>>()?;
+        Ok(records)
+    }
+
+    /// The mutations made to `bd` since the records stored were saved, or
+    /// `None` unless the journal of this database holds all of them.
+    fn unsaved(&self, bd: &BazaDate) -> Result<Option<Vec<Mutation>>, DatabaseError> {
+        let journal = match &bd.journal {
+            Some(journal) if *journal == Journal::for_database(&self.path) => journal,
+            _ => return Ok(None),
+        };
+        let saved = self.meta("sequence")?.unwrap_or(0) as u64;
+        if saved > bd.seq {
+            return Ok(None);
+        }
+        let entries: Vec<(u64, Mutation)> = journal
+            .entries()?
+            .into_iter()
+            .filter(|(seq, _)| *seq > saved)
+            .collect();
+        if !entries.iter().map(|(seq, _)| *seq).eq(saved + 1..=bd.seq) {
+            return Ok(None);
+        }
+        Ok(Some(
+            entries.into_iter().map(|(_, mutation)| mutation).collect(),
+        ))
+    }
+}
+
+/// The statement creating the table of `R`, if it does not exist yet.
+fn create_table<R: Record>() -> String {
+    let schema = &R::SCHEMA;
+    let columns: Vec<String> = schema
+        .columns
+        .iter()
+        .map(|column| {
+            let kind = match column.kind {
+                ColumnType::Integer => "INTEGER",
+                ColumnType::Text | ColumnType::Phone => "TEXT",
+            };
+            let references = match column.references {
+                Some(table) => format!(" REFERENCES {}", table),
+                None => String::new(),
+            };
+            format!(
+                ",\n                 {} {} NOT NULL{}",
+                column.name, kind, references
+            )
+        })
+        .collect();
+    format!(
+        "CREATE TABLE IF NOT EXISTS {} (
+                 {} INTEGER PRIMARY KEY{}
+             );",
+        schema.name,
+        schema.id,
+        columns.concat()
+    )
+}
+
+/// Builds a record from a row holding its id followed by its columns.
+fn read_row<R: Record>(row: &Row) -> rusqlite::Result<R> {
+    let mut record = R::default();
+    record.set_id(row.get(0)?);
+    for (index, column) in R::SCHEMA.columns.iter().enumerate() {
+        let value = match row.get_ref(index + 1)? {
+            ValueRef::Integer(number) => number.to_string(),
+            _ => row.get(index + 1)?,
+        };
+        (column.set)(&mut record, value);
+    }
+    Ok(record)
+}
+
+/// Inserts the records into the table of `R`, replacing the columns of
+/// those whose id is already there.
+fn upsert_all<R: Record>(transaction: &Transaction, records: &[R]) -> Result<(), DatabaseError> {
+    let schema = &R::SCHEMA;
+    let placeholders = vec!["?"; schema.columns.len() + 1].join(", ");
+    let updates: Vec<String> = schema
+        .columns
+        .iter()
+        .map(|column| format!("{0} = excluded.{0}", column.name))
+        .collect();
+    let mut upsert = transaction.prepare_cached(&format!(
+        "INSERT INTO {} ({}, {}) VALUES ({}) ON CONFLICT ({}) DO UPDATE SET {}",
+        schema.name,
+        schema.id,
+        schema.column_names(),
+        placeholders,
+        schema.id,
+        updates.join(", ")
+    ))?;
+    for record in records {
+        let mut values = vec![Value::Integer(record.id().into())];
+        for column in schema.columns {
+            let value = (column.get)(record);
+            values.push(match (column.kind, value.parse::<i64>()) {
+                (ColumnType::Integer, Ok(number)) => Value::Integer(number),
+                _ => Value::Text(value),
+            });
+        }
+        upsert.execute(params_from_iter(values))?;
+    }
+    Ok(())
+}
+
+/// Deletes the record of `R` with the given id.
+fn delete<R: Record>(transaction: &Transaction, id: i32) -> Result<(), DatabaseError> {
+    let schema = &R::SCHEMA;
+    transaction
+        .prepare_cached(&format!(
+            "DELETE FROM {} WHERE {} = ?1",
+            schema.name, schema.id
+        ))?
+        .execute([id])?;
+    Ok(())
+}
+
+impl Storage for SqliteStorage {
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn read(&self) -> Result<BazaDate, DatabaseError> {
+        let mut bd = BazaDate::new();
+        bd.clienti = self.query("ORDER BY nr_ordine", [])?;
+        bd.comenzi = self.query("ORDER BY nr_comanda", [])?;
+        let highest = bd.clienti.iter().map(|c| c.nr_ordine).max().unwrap_or(-1);
+        bd.top = self.meta("top")?.map_or(-1, |top| top as i32).max(highest);
+        let highest = bd.comenzi.iter().map(|c| c.nr_comanda).max().unwrap_or(-1);
+        bd.top_comenzi = self
+            .meta("top_comenzi")?
+            .map_or(-1, |top| top as i32)
+            .max(highest);
+        bd.seq = self.meta("sequence")?.unwrap_or(0) as u64;
+        Ok(bd)
+    }
+
+    /// Only the mutations journaled since the last save are applied, as
+    /// upserts and deletes. When the journal of this database does not hold
+    /// every one of them, as after `migrate`, all records are replaced.
+    /// Either way it happens in one transaction, so readers see either the
+    /// previous records or the new ones.
+    fn write(&self, bd: &BazaDate) -> Result<(), DatabaseError> {
+        let unsaved = self.unsaved(bd)?;
+        let transaction = self.connection.unchecked_transaction()?;
+        match unsaved {
+            Some(mutations) => {
+                // The journal keeps mutations in the order they were made,
+                // so no foreign key is broken along the way.
+                for mutation in mutations {
+                    match mutation {
+                        Mutation::Add(client) | Mutation::Update(client) => {
+                            upsert_all(&transaction, &[client])?
+                        }
+                        Mutation::Delete(nr_ordine) => delete::<Client>(&transaction, nr_ordine)?,
+                        Mutation::AddOrder(order) => upsert_all(&transaction, &[order])?,
+                        Mutation::DeleteOrder(nr_comanda) => {
+                            delete::<Comanda>(&transaction, nr_comanda)?
+                        }
+                    }
+                }
+            }
+            None => {
+                // Orders go first and come back last, so no foreign key is
+                // ever broken.
+                transaction.execute(&format!("DELETE FROM {}", Comanda::SCHEMA.name), [])?;
+                transaction.execute(&format!("DELETE FROM {}", Client::SCHEMA.name), [])?;
+                upsert_all(&transaction, &bd.clienti)?;
+                upsert_all(&transaction, &bd.comenzi)?;
+            }
+        }
+        {
+            let mut meta =
+                transaction.prepare("INSERT OR REPLACE INTO meta (key, value) VALUES (?1, ?2)")?;
+            meta.execute(params!["top", bd.top])?;
+            meta.execute(params!["top_comenzi", bd.top_comenzi])?;
+            meta.execute(params!["sequence", bd.seq as i64])?;
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    fn count(&self) -> Result<usize, DatabaseError> {
+        let count: i64 = self.connection.query_row(
+            &format!("SELECT COUNT(*) FROM {}", Client::SCHEMA.name),
+            [],
+            |row| row.get(0),
+        )?;
+        Ok(count as usize)
+    }
+
+    fn page(&self, offset: usize, limit: usize) -> Result<Vec<Client>, DatabaseError> {
+        self.query(
+            "ORDER BY nr_ordine LIMIT ?1 OFFSET ?2",
+            params![limit as i64, offset as i64],
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{add_clients, TestDir};
+    use crate::types::OnDelete;
+
+    #[test]
+    fn write_then_read_keeps_ids_and_pages() {
+        let dir = TestDir::new("sqlite");
+        let path = dir.path().join("clienti.sqlite");
+
+        let mut bd = BazaDate::new();
+        let ids = add_clients(&mut bd, &["Popescu", "Ionescu", "Stanciu"]);
+        bd.delete_record(ids[0], OnDelete::Restrict).unwrap();
+
+        let storage = SqliteStorage::open(&path).unwrap();
+        storage.write(&bd).unwrap();
+        drop(storage);
+
+        let storage = SqliteStorage::open(&path).unwrap();
+        assert_eq!(storage.read().unwrap(), bd);
+        assert_eq!(storage.count().unwrap(), 2);
+        assert_eq!(storage.page(1, 10).unwrap(), vec![bd.clienti[1].clone()]);
+    }
+
+    #[test]
+    fn saves_apply_only_the_journaled_mutations() {
+        let dir = TestDir::new("sqlite-journal");
+        let storage = SqliteStorage::open(&dir.path().join("clienti.sqlite")).unwrap();
+        let mut bd = storage.load().unwrap();
+        let ids = add_clients(&mut bd, &["a", "b", "c"]);
+        storage.write(&bd).unwrap();
+        bd.journal.as_ref().unwrap().compact().unwrap();
+
+        // A record the journal knows nothing about stays as it is, as only
+        // the records mutated since the last save are written.
+        storage
+            .connection
+            .execute(
+                "INSERT INTO clienti (nr_ordine, prenume, nume, nr_telefon, adresa)
+                 VALUES (100, '', 'd', '', '')",
+                [],
+            )
+            .unwrap();
+        bd.update_record(
+            ids[0],
+            Client {
+                nume: String::from("a2"),
+                ..Client::new()
+            },
+        )
+        .unwrap();
+        bd.delete_record(ids[1], OnDelete::Restrict).unwrap();
+        storage.write(&bd).unwrap();
+        assert_eq!(
+            storage
+                .query::<Client>("WHERE nr_ordine < 100", [])
+                .unwrap(),
+            bd.clienti
+        );
+        assert_eq!(storage.count().unwrap(), 3);
+        assert_eq!(storage.meta("sequence").unwrap(), Some(bd.seq as i64));
+
+        // Without the journal everything is replaced.
+        bd.journal = None;
+        storage.write(&bd).unwrap();
+        assert_eq!(storage.read().unwrap().clienti, bd.clienti);
+    }
+}
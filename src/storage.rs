@@ -0,0 +1,814 @@
+use std::{
+    collections::HashSet,
+    env,
+    ffi::OsString,
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use thiserror::Error;
+
+use crate::audit::AuditLog;
+use crate::journal::Journal;
+use crate::schema::Record;
+use crate::sqlite::SqliteStorage;
+use crate::types::{BazaDate, Client, Comanda};
+
+/// First line of every database file written in the current format.
+const DATABASE_MAGIC: &str = "db_cli-rs database";
+/// Version of the format written by `serialize_database`.
+const DATABASE_VERSION: u32 = 4;
+/// Oldest version of the format that can still be read, which has no orders.
+/// Before version 4 ids were not stored and came from the record positions.
+const OLDEST_VERSION: u32 = 2;
+/// Line separating the header from the records, and the clients from the orders.
+const HEADER_END: &str = "---";
+
+/// Environment variable holding the number of backups kept next to the database.
+const BACKUPS_VAR: &str = "DB_CLI_BACKUPS";
+/// Environment variable holding the autosave interval, in seconds.
+const AUTOSAVE_VAR: &str = "DB_CLI_AUTOSAVE";
+
+/// How the database is written to disk.
+#[derive(Clone, Copy, Debug)]
+pub struct SaveOptions {
+    /// Number of `.bak` generations kept next to the database.
+    pub backups: usize,
+    /// How often unsaved changes are written while the app runs, if at all.
+    pub autosave: Option<Duration>,
+}
+
+impl Default for SaveOptions {
+    fn default() -> SaveOptions {
+        SaveOptions {
+            backups: 3,
+            autosave: None,
+        }
+    }
+}
+
+impl SaveOptions {
+    /// Reads the options from the environment, falling back to the defaults.
+    pub fn from_env() -> SaveOptions {
+        let mut options = SaveOptions::default();
+        if let Some(backups) = env::var(BACKUPS_VAR).ok().and_then(|v| v.parse().ok()) {
+            options.backups = backups;
+        }
+        if let Some(seconds) = env::var(AUTOSAVE_VAR).ok().and_then(|v| v.parse().ok()) {
+            options.autosave = match seconds {
+                0 => None,
+                seconds => Some(Duration::from_secs(seconds)),
+            };
+        }
+        options
+    }
+}
+
+/// The order of the fields in legacy files and in journals without a
+/// `fields` line, which predate the schema.
+pub(crate) const LEGACY_FIELDS: [&str; 4] = ["nume", "prenume", "nr_telefon", "adresa"];
+
+/// Maps stored field names to the columns of the schema of `R`.
+pub(crate) fn field_columns<'a, R: Record>(
+    names: impl IntoIterator<Item = &'a str>,
+) -> Result<Vec<usize>, DatabaseError> {
+    names
+        .into_iter()
+        .map(|name| {
+            R::SCHEMA
+                .column(name)
+                .ok_or_else(|| DatabaseError::UnknownField(name.to_string()))
+        })
+        .collect()
+}
+
+/// The names of the columns of `R`, as written in the `fields` headers.
+pub(crate) fn field_names<R: Record>() -> Vec<&'static str> {
+    R::SCHEMA.columns.iter().map(|column| column.name).collect()
+}
+
+/// Maps the names of a `fields` header of the database file to columns,
+/// telling whether every line starts with the id of its record.
+fn stored_columns<R: Record>(names: Vec<&str>) -> Result<(bool, Vec<usize>), DatabaseError> {
+    match names.split_first() {
+        Some((&first, rest)) if first == R::SCHEMA.id => {
+            Ok((true, field_columns::<R>(rest.iter().copied())?))
+        }
+        _ => Ok((false, field_columns::<R>(names)?)),
+    }
+}
+
+/// Errors raised while reading a database file.
+#[derive(Debug, Error)]
+pub enum DatabaseError {
+    #[error("missing `{0}` header")]
+    MissingHeader(&'static str),
+    #[error("invalid value `{value}` for the `{key}` header")]
+    InvalidHeader { key: String, value: String },
+    #[error("unsupported format version {0}")]
+    UnsupportedVersion(u32),
+    #[error("unknown field `{0}`")]
+    UnknownField(String),
+    #[error("line {line}: expected {expected} fields, found {found}")]
+    FieldCount {
+        line: usize,
+        expected: usize,
+        found: usize,
+    },
+    #[error("line {line}: invalid escape sequence `\\{sequence}`")]
+    InvalidEscape { line: usize, sequence: String },
+    #[error("line {line}: invalid id `{value}`")]
+    InvalidId { line: usize, value: String },
+    #[error("line {line}: id {id} is used by an earlier record")]
+    DuplicateId { line: usize, id: i32 },
+    #[error("the header declares {expected} records but the file holds {found}")]
+    RecordCount { expected: usize, found: usize },
+    #[error("the legacy file has {lines} lines, which is not {fields} per record")]
+    TruncatedLegacy { lines: usize, fields: usize },
+    #[error("journal line {line}: {message}")]
+    Journal { line: usize, message: String },
+    #[error(transparent)]
+    Sqlite(#[from] rusqlite::Error),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+/// Where a database is kept between runs.
+///
+/// Mutations made in between are recorded in the journal next to `path`,
+/// whatever the backend.
+pub trait Storage {
+    /// The path of the stored database.
+    fn path(&self) -> &Path;
+
+    /// Reads every stored record, without replaying the journal.
+    fn read(&self) -> Result<BazaDate, DatabaseError>;
+
+    /// Replaces the stored records with those of the database.
+    fn write(&self, bd: &BazaDate) -> Result<(), DatabaseError>;
+
+    /// The number of stored records.
+    fn count(&self) -> Result<usize, DatabaseError>;
+
+    /// Reads at most `limit` stored records, by increasing `nr_ordine`,
+    /// after skipping the first `offset`.
+    fn page(&self, offset: usize, limit: usize) -> Result<Vec<Client>, DatabaseError>;
+
+    /// Reads every stored record by increasing `nr_ordine`, `size` at a time.
+    fn pages(&self, size: usize) -> Pages<'_> {
+        let mut offset = 0;
+        let mut done = false;
+        Box::new(std::iter::from_fn(move || {
+            if done {
+                return None;
+            }
+            match self.page(offset, size) {
+                Ok(page) if page.is_empty() => None,
+                Ok(page) => {
+                    offset += page.len();
+                    Some(Ok(page))
+                }
+                Err(error) => {
+                    done = true;
+                    Some(Err(error))
+                }
+            }
+        }))
+    }
+
+    /// Reads the database, replays the mutations its journal recorded
+    /// after the last write, and keeps journaling to the same file.
+    fn load(&self) -> Result<BazaDate, DatabaseError> {
+        let mut bd = self.read()?;
+        let journal = Journal::for_database(self.path());
+        journal.replay(&mut bd)?;
+        bd.journal = Some(journal);
+        bd.audit = Some(AuditLog::for_database(self.path()));
+        Ok(bd)
+    }
+}
+
+/// The pages of records returned by `Storage::pages`.
+pub type Pages<'a> = Box<dyn Iterator<Item = Result<Vec<Client>, DatabaseError>> + 'a>;
+
+/// Opens the storage for `path`: SQLite for `.sqlite`, `.sqlite3` and `.db`
+/// files, the flat file format for anything else.
+pub fn open_storage(path: &Path, options: SaveOptions) -> Result<Box<dyn Storage>, DatabaseError> {
+    let extension = path.extension().and_then(|extension| extension.to_str());
+    Ok(match extension {
+        Some("sqlite" | "sqlite3" | "db") => Box::new(SqliteStorage::open(path)?),
+        _ => Box::new(FileStorage::new(path, options)),
+    })
+}
+
+/// A database kept in the flat file format.
+pub struct FileStorage {
+    path: PathBuf,
+    options: SaveOptions,
+}
+
+impl FileStorage {
+    pub fn new(path: &Path, options: SaveOptions) -> FileStorage {
+        FileStorage {
+            path: path.to_path_buf(),
+            options,
+        }
+    }
+}
+
+impl Storage for FileStorage {
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// A missing file is an empty database.
+    fn read(&self) -> Result<BazaDate, DatabaseError> {
+        match fs::read_to_string(&self.path) {
+            Ok(file_contents) => create_database(file_contents),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(BazaDate::new()),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    fn write(&self, bd: &BazaDate) -> Result<(), DatabaseError> {
+        Ok(save_database(&self.path, bd, &self.options)?)
+    }
+
+    fn count(&self) -> Result<usize, DatabaseError> {
+        Ok(self.read()?.clienti.len())
+    }
+
+    /// The file can't be read partially, so it is read whole and sliced.
+    /// Reading every page should go through `pages`, which reads it once.
+    fn page(&self, offset: usize, limit: usize) -> Result<Vec<Client>, DatabaseError> {
+        let bd = self.read()?;
+        Ok(bd.clienti.into_iter().skip(offset).take(limit).collect())
+    }
+
+    /// Reads the file once instead of once per page.
+    fn pages(&self, size: usize) -> Pages<'_> {
+        match self.read() {
+            Ok(bd) => {
+                let pages: Vec<Vec<Client>> =
+                    bd.clienti.chunks(size).map(<[Client]>::to_vec).collect();
+                Box::new(pages.into_iter().map(Ok))
+            }
+            Err(error) => Box::new(std::iter::once(Err(error))),
+        }
+    }
+}
+
+/// Creating the database using the `String` provided.
+///
+/// Files starting with the format header are read by `read_database`,
+/// anything else is treated as the legacy four-lines-per-record layout.
+pub fn create_database(file_contents: String) -> Result<BazaDate, DatabaseError> {
+    match file_contents.lines().next() {
+        Some(line) if line.trim_end() == DATABASE_MAGIC => read_database(&file_contents),
+        _ => read_legacy_database(&file_contents),
+    }
+}
+
+/// Serializes the database in the current file format.
+pub fn serialize_database(bd: &BazaDate) -> String {
+    let stored_names = |id: &str, names: Vec<&str>| {
+        std::iter::once(id)
+            .chain(names)
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    let mut data = String::new();
+    data.push_str(DATABASE_MAGIC);
+    data.push('\n');
+    data.push_str(&format!("version: {}\n", DATABASE_VERSION));
+    data.push_str(&format!(
+        "fields: {}\n",
+        stored_names(Client::SCHEMA.id, field_names::<Client>())
+    ));
+    data.push_str(&format!("records: {}\n", bd.clienti.len()));
+    data.push_str(&format!("top: {}\n", bd.top));
+    data.push_str(&format!(
+        "order_fields: {}\n",
+        stored_names(Comanda::SCHEMA.id, field_names::<Comanda>())
+    ));
+    data.push_str(&format!("orders: {}\n", bd.comenzi.len()));
+    data.push_str(&format!("top_orders: {}\n", bd.top_comenzi));
+    data.push_str(&format!("sequence: {}\n", bd.seq));
+    data.push_str(HEADER_END);
+    data.push('\n');
+
+    for client in bd.clienti.iter() {
+        data.push_str(&format!(
+            "{}\t{}\n",
+            client.nr_ordine,
+            encode_fields(client)
+        ));
+    }
+    data.push_str(HEADER_END);
+    data.push('\n');
+    for order in bd.comenzi.iter() {
+        data.push_str(&format!("{}\t{}\n", order.nr_comanda, encode_fields(order)));
+    }
+
+    data
+}
+
+/// Escapes the stored fields of a record and joins them with tabs,
+/// in the order of the schema.
+pub(crate) fn encode_fields<R: Record>(record: &R) -> String {
+    let line: Vec<String> = R::SCHEMA
+        .values(record)
+        .iter()
+        .map(|value| escape_field(value))
+        .collect();
+    line.join("\t")
+}
+
+/// Builds a record from escaped values stored in the order of `columns`.
+pub(crate) fn decode_fields<R: Record>(values: &[&str], columns: &[usize]) -> Result<R, String> {
+    if values.len() != columns.len() {
+        return Err(format!(
+            "expected {} fields, found {}",
+            columns.len(),
+            values.len()
+        ));
+    }
+
+    let mut record = R::default();
+    for (&column, value) in columns.iter().zip(values) {
+        let value = unescape_field(value)
+            .map_err(|sequence| format!("invalid escape sequence `\\{}`", sequence))?;
+        (R::SCHEMA.columns[column].set)(&mut record, value);
+    }
+    Ok(record)
+}
+
+/// Saves the database to `path` without ever truncating the live file.
+///
+/// The data is written and fsynced to a temporary file next to `path`,
+/// the previous file is rotated into the backups, and the temporary file
+/// is then renamed over the original.
+pub fn save_database(path: &Path, bd: &BazaDate, options: &SaveOptions) -> io::Result<()> {
+    let temp_path = sibling_path(path, ".tmp");
+    {
+        let mut file = File::create(&temp_path)?;
+        file.write_all(serialize_database(bd).as_bytes())?;
+        file.sync_all()?;
+    }
+
+    if path.exists() {
+        rotate_backups(path, options.backups)?;
+    }
+    fs::rename(&temp_path, path)?;
+    sync_parent_dir(path)
+}
+
+/// Returns the path of the given backup generation, `1` being the newest.
+fn backup_path(path: &Path, generation: usize) -> PathBuf {
+    sibling_path(path, &format!(".{}.bak", generation))
+}
+
+/// Shifts every backup one generation back and copies the live file into
+/// the newest one, dropping whatever falls past `backups`.
+fn rotate_backups(path: &Path, backups: usize) -> io::Result<()> {
+    if backups == 0 {
+        return Ok(());
+    }
+
+    let oldest = backup_path(path, backups);
+    if oldest.exists() {
+        fs::remove_file(&oldest)?;
+    }
+    for generation in (1..backups).rev() {
+        let backup = backup_path(path, generation);
+        if backup.exists() {
+            fs::rename(&backup, backup_path(path, generation + 1))?;
+        }
+    }
+    fs::copy(path, backup_path(path, 1))?;
+    Ok(())
+}
+
+/// Appends `suffix` to the file name of `path`.
+pub(crate) fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut name: OsString = path.file_name().unwrap_or_default().to_owned();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+/// Opens a file of newline-terminated lines for appending.
+///
+/// A last line without its newline was cut short by a crash while being
+/// appended, so it is cut off first: appending after it would glue the new
+/// line to the fragment and leave a corrupt line in the middle of the file.
+pub(crate) fn open_for_append(path: &Path) -> io::Result<File> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .read(true)
+        .write(true)
+        .truncate(false)
+        .open(path)?;
+    let mut end = file.metadata()?.len();
+    let mut buffer = [0; 4096];
+    while end > 0 {
+        let start = end.saturating_sub(buffer.len() as u64);
+        let chunk = &mut buffer[..(end - start) as usize];
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(chunk)?;
+        match chunk.iter().rposition(|&byte| byte == b'\n') {
+            Some(newline) => {
+                end = start + newline as u64 + 1;
+                break;
+            }
+            None => end = start,
+        }
+    }
+    if end < file.metadata()?.len() {
+        file.set_len(end)?;
+    }
+    file.seek(SeekFrom::End(0))?;
+    Ok(file)
+}
+
+/// Makes the rename of the database file durable.
+#[cfg(unix)]
+fn sync_parent_dir(path: &Path) -> io::Result<()> {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => File::open(dir)?.sync_all(),
+        _ => File::open(".")?.sync_all(),
+    }
+}
+
+/// Directories can't be opened for syncing on this platform.
+#[cfg(not(unix))]
+fn sync_parent_dir(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// Reads a database written in the current file format, or in version 2,
+/// which has no orders.
+fn read_database(file_contents: &str) -> Result<BazaDate, DatabaseError> {
+    let mut lines = file_contents.lines().enumerate().skip(1);
+
+    let mut version = None;
+    let mut fields = None;
+    let mut records = None;
+    let mut order_fields = None;
+    let mut orders = None;
+    let mut top = -1;
+    let mut top_orders = -1;
+    let mut sequence = 0;
+    for (_, line) in lines.by_ref() {
+        let line = line.trim_end_matches('\r');
+        if line == HEADER_END {
+            break;
+        }
+        let (key, value) = match line.split_once(':') {
+            Some((key, value)) => (key.trim(), value.trim()),
+            None => continue,
+        };
+        let invalid = || DatabaseError::InvalidHeader {
+            key: key.to_string(),
+            value: value.to_string(),
+        };
+        let names = || value.split(',').map(str::trim).collect::<Vec<_>>();
+        match key {
+            "version" => version = Some(value.parse::<u32>().map_err(|_| invalid())?),
+            "records" => records = Some(value.parse::<usize>().map_err(|_| invalid())?),
+            "orders" => orders = Some(value.parse::<usize>().map_err(|_| invalid())?),
+            "top" => top = value.parse::<i32>().map_err(|_| invalid())?,
+            "top_orders" => top_orders = value.parse::<i32>().map_err(|_| invalid())?,
+            "sequence" => sequence = value.parse::<u64>().map_err(|_| invalid())?,
+            "fields" => fields = Some(names()),
+            "order_fields" => order_fields = Some(names()),
+            _ => {}
+        }
+    }
+
+    let version = version.ok_or(DatabaseError::MissingHeader("version"))?;
+    if !(OLDEST_VERSION..=DATABASE_VERSION).contains(&version) {
+        return Err(DatabaseError::UnsupportedVersion(version));
+    }
+    let records = records.ok_or(DatabaseError::MissingHeader("records"))?;
+    let (client_ids, fields) =
+        stored_columns::<Client>(fields.ok_or(DatabaseError::MissingHeader("fields"))?)?;
+    let (orders, (order_ids, order_fields)) = match version {
+        OLDEST_VERSION => (0, (false, Vec::new())),
+        _ => (
+            orders.ok_or(DatabaseError::MissingHeader("orders"))?,
+            stored_columns::<Comanda>(
+                order_fields.ok_or(DatabaseError::MissingHeader("order_fields"))?,
+            )?,
+        ),
+    };
+
+    let mut bd = BazaDate::new();
+    bd.top = top;
+    bd.top_comenzi = top_orders;
+    bd.seq = sequence;
+    let (mut seen_clients, mut seen_orders) = (HashSet::new(), HashSet::new());
+    let mut in_orders = false;
+    for (index, line) in lines {
+        let line = line.trim_end_matches('\r');
+        if line.is_empty() {
+            continue;
+        }
+        if line == HEADER_END && version > OLDEST_VERSION && !in_orders {
+            in_orders = true;
+            continue;
+        }
+        // Without stored ids, records are numbered by their position, which
+        // is also how orders of version 3 point to their client.
+        match in_orders {
+            false => {
+                let mut client: Client = read_record(line, index + 1, &fields, client_ids)?;
+                if !client_ids {
+                    client.nr_ordine = bd.clienti.len() as i32;
+                }
+                if !seen_clients.insert(client.nr_ordine) {
+                    return Err(DatabaseError::DuplicateId {
+                        line: index + 1,
+                        id: client.nr_ordine,
+                    });
+                }
+                bd.push_record(client);
+            }
+            true => {
+                let mut order: Comanda = read_record(line, index + 1, &order_fields, order_ids)?;
+                if !order_ids {
+                    order.nr_comanda = bd.comenzi.len() as i32;
+                }
+                if !seen_orders.insert(order.nr_comanda) {
+                    return Err(DatabaseError::DuplicateId {
+                        line: index + 1,
+                        id: order.nr_comanda,
+                    });
+                }
+                bd.push_order(order);
+            }
+        }
+    }
+
+    if bd.clienti.len() != records {
+        return Err(DatabaseError::RecordCount {
+            expected: records,
+            found: bd.clienti.len(),
+        });
+    }
+    if bd.comenzi.len() != orders {
+        return Err(DatabaseError::RecordCount {
+            expected: orders,
+            found: bd.comenzi.len(),
+        });
+    }
+
+    Ok(bd)
+}
+
+/// Reads the record on line `number`, whose fields are in the order of
+/// `columns`, after its id if `with_id` is set.
+fn read_record<R: Record>(
+    line: &str,
+    number: usize,
+    columns: &[usize],
+    with_id: bool,
+) -> Result<R, DatabaseError> {
+    let mut values: Vec<&str> = line.split('\t').collect();
+    let expected = columns.len() + with_id as usize;
+    if values.len() != expected {
+        return Err(DatabaseError::FieldCount {
+            line: number,
+            expected,
+            found: values.len(),
+        });
+    }
+
+    let mut record = R::default();
+    if with_id {
+        let id = values.remove(0);
+        record.set_id(id.parse().map_err(|_| DatabaseError::InvalidId {
+            line: number,
+            value: id.to_string(),
+        })?);
+    }
+    for (&column, value) in columns.iter().zip(values) {
+        let value = unescape_field(value).map_err(|sequence| DatabaseError::InvalidEscape {
+            line: number,
+            sequence,
+        })?;
+        (R::SCHEMA.columns[column].set)(&mut record, value);
+    }
+    Ok(record)
+}
+
+/// Reads the legacy layout, where every record takes exactly four lines.
+/// A trailing record with missing lines is an error rather than data loss.
+fn read_legacy_database(file_contents: &str) -> Result<BazaDate, DatabaseError> {
+    let mut bd: BazaDate = BazaDate::new();
+    let lines: Vec<&str> = file_contents.lines().collect();
+    if !lines.len().is_multiple_of(LEGACY_FIELDS.len()) {
+        return Err(DatabaseError::TruncatedLegacy {
+            lines: lines.len(),
+            fields: LEGACY_FIELDS.len(),
+        });
+    }
+
+    for record in lines.chunks_exact(LEGACY_FIELDS.len()) {
+        let mut temp: Client = Client::new();
+        temp.nr_ordine = bd.clienti.len() as i32;
+        for (name, value) in LEGACY_FIELDS.iter().zip(record) {
+            let column = Client::SCHEMA.column(name).expect("legacy field in schema");
+            (Client::SCHEMA.columns[column].set)(&mut temp, value.replace('\r', ""));
+        }
+        bd.push_record(temp);
+    }
+
+    Ok(bd)
+}
+
+/// Escapes the characters that would break the one-record-per-line layout.
+pub(crate) fn escape_field(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Reverses `escape_field`, returning the offending sequence on error.
+fn unescape_field(value: &str) -> Result<String, String> {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('\\') => unescaped.push('\\'),
+            Some('t') => unescaped.push('\t'),
+            Some('n') => unescaped.push('\n'),
+            Some('r') => unescaped.push('\r'),
+            Some(other) => return Err(other.to_string()),
+            None => return Err(String::new()),
+        }
+    }
+    Ok(unescaped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{add_clients, save_options, TestDir};
+    use crate::types::{IntegrityError, OnDelete};
+    use proptest::prelude::*;
+
+    fn client_strategy() -> impl Strategy<Value = Client> {
+        let value = || prop_oneof![any::<String>(), "[a-zA-Z0-9 \\\\\t\r\n-]{0,12}"];
+        (value(), value(), value(), value()).prop_map(|(nume, prenume, nr_telefon, adresa)| {
+            Client {
+                nr_ordine: 0,
+                nume,
+                prenume,
+                nr_telefon,
+                adresa,
+            }
+        })
+    }
+
+    fn database_strategy() -> impl Strategy<Value = BazaDate> {
+        prop::collection::vec(client_strategy(), 0..20).prop_map(|clients| {
+            let mut bd = BazaDate::new();
+            for client in clients {
+                bd.add_record(client).unwrap();
+            }
+            bd
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn save_then_load_is_identity(bd in database_strategy()) {
+            let loaded = create_database(serialize_database(&bd)).unwrap();
+            prop_assert_eq!(loaded, bd);
+        }
+
+        #[test]
+        fn escaping_round_trips(value in any::<String>()) {
+            let escaped = escape_field(&value);
+            prop_assert!(!escaped.contains(['\t', '\n', '\r']));
+            prop_assert_eq!(unescape_field(&escaped).unwrap(), value);
+        }
+    }
+
+    #[test]
+    fn save_rotates_backups() {
+        let dir = TestDir::new("save");
+        let path = dir.database();
+        let options = SaveOptions {
+            backups: 2,
+            ..save_options()
+        };
+
+        let mut bd = BazaDate::new();
+        for name in ["a", "b", "c", "d"] {
+            add_clients(&mut bd, &[name]);
+            save_database(&path, &bd, &options).unwrap();
+        }
+
+        let read = |path: &Path| create_database(fs::read_to_string(path).unwrap()).unwrap();
+        assert_eq!(read(&path).clienti.len(), 4);
+        assert_eq!(read(&backup_path(&path, 1)).clienti.len(), 3);
+        assert_eq!(read(&backup_path(&path, 2)).clienti.len(), 2);
+        assert!(!backup_path(&path, 3).exists());
+        assert!(!sibling_path(&path, ".tmp").exists());
+    }
+
+    #[test]
+    fn orders_follow_their_client_across_deletes_and_reloads() {
+        let mut bd = BazaDate::new();
+        add_clients(&mut bd, &["a", "b", "c"]);
+        for nr_client in [1, 2, 2] {
+            bd.add_order(Comanda {
+                nr_client,
+                produs: String::from("Laptop"),
+                cantitate: 1,
+                ..Comanda::default()
+            })
+            .unwrap();
+        }
+
+        assert!(matches!(
+            bd.delete_record(2, OnDelete::Restrict),
+            Err(IntegrityError::HasOrders { id: 2, orders: 2 })
+        ));
+        bd.delete_record(0, OnDelete::Restrict).unwrap();
+        bd.delete_record(2, OnDelete::Cascade).unwrap();
+        assert_eq!(bd.comenzi.len(), 1);
+
+        let mut loaded = create_database(serialize_database(&bd)).unwrap();
+        assert_eq!(loaded, bd);
+        let order = &loaded.comenzi[0];
+        assert_eq!(loaded.get_record(order.nr_client).unwrap().nume, "b");
+        assert_eq!(loaded.add_record(Client::new()).unwrap(), 3);
+    }
+
+    #[test]
+    fn version_3_orders_point_to_client_positions() {
+        let bd = create_database(String::from(
+            "db_cli-rs database\n\
+             version: 3\n\
+             fields: prenume, nume, nr_telefon, adresa\n\
+             records: 2\n\
+             order_fields: nr_client, produs, cantitate, data\n\
+             orders: 1\n\
+             ---\n\
+             Ion\tPop\t\t\n\
+             Ana\tDan\t\t\n\
+             ---\n\
+             1\tLaptop\t2\t\n",
+        ))
+        .unwrap();
+        assert_eq!(bd.get_record(1).unwrap().prenume, "Ana");
+        assert_eq!(bd.orders_of(1).count(), 1);
+        assert_eq!((bd.top, bd.top_comenzi), (1, 0));
+    }
+
+    #[test]
+    fn legacy_file_keeps_last_name_first() {
+        let bd = create_database(String::from("Stanciu\nBogdan\n0722\nstr. 1\n")).unwrap();
+        assert_eq!(bd.clienti[0].nume, "Stanciu");
+        assert_eq!(bd.clienti[0].prenume, "Bogdan");
+
+        let reloaded = create_database(serialize_database(&bd)).unwrap();
+        assert_eq!(reloaded, bd);
+    }
+
+    #[test]
+    fn legacy_file_without_trailing_newline_keeps_last_record() {
+        let bd = create_database(String::from("Stanciu\nBogdan\n0722\nstr. 1")).unwrap();
+        assert_eq!(bd.clienti.len(), 1);
+        assert_eq!(bd.clienti[0].adresa, "str. 1");
+    }
+
+    #[test]
+    fn legacy_file_with_a_truncated_record_is_rejected() {
+        let error = create_database(String::from("Stanciu\nBogdan\n0722\nstr. 1\nPop\n"));
+        assert!(matches!(
+            error,
+            Err(DatabaseError::TruncatedLegacy {
+                lines: 5,
+                fields: 4
+            })
+        ));
+    }
+}
@@ -0,0 +1,69 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::storage::SaveOptions;
+use crate::types::{BazaDate, Client};
+
+/// Tells apart the directories made by one test run.
+static NEXT_DIR: AtomicUsize = AtomicUsize::new(0);
+
+/// A directory of its own for a single test, removed when dropped, even if
+/// the test fails.
+pub struct TestDir {
+    path: PathBuf,
+}
+
+impl TestDir {
+    /// Makes a new, empty directory whose name starts with `name`.
+    pub fn new(name: &str) -> TestDir {
+        let path = std::env::temp_dir().join(format!(
+            "db_cli-rs-{}-{}-{}",
+            name,
+            std::process::id(),
+            NEXT_DIR.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TestDir { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The path of a database file in the directory.
+    pub fn database(&self) -> PathBuf {
+        self.path.join("baza_de_date.io")
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+/// Saves without backups or autosave.
+pub fn save_options() -> SaveOptions {
+    SaveOptions {
+        backups: 0,
+        autosave: None,
+    }
+}
+
+/// Adds a client for each last name, returning their ids.
+pub fn add_clients(bd: &mut BazaDate, names: &[&str]) -> Vec<i32> {
+    names
+        .iter()
+        .map(|nume| {
+            bd.add_record(Client {
+                nume: nume.to_string(),
+                ..Client::new()
+            })
+            .unwrap()
+        })
+        .collect()
+}
@@ -1,25 +1,18 @@
-use std::collections::LinkedList;
+use std::{
+    io,
+    path::{Path, PathBuf},
+    time::Instant,
+};
 
-use tui::widgets::Row;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
 
-#[derive(Copy, Clone, PartialEq)]
-pub enum QueryMode {
-    FirstName,
-    LastName,
-    PhoneNumber,
-    Address,
-}
-
-impl From<QueryMode> for usize {
-    fn from(input: QueryMode) -> usize {
-        match input {
-            QueryMode::FirstName => 0,
-            QueryMode::LastName => 1,
-            QueryMode::PhoneNumber => 2,
-            QueryMode::Address => 3,
-        }
-    }
-}
+use crate::audit::{AuditEntry, AuditLog};
+use crate::history::History;
+use crate::journal::{Journal, Mutation};
+use crate::query::{ParseError, Query, SearchMode};
+use crate::schema::{Column, ColumnType, Record, Rule, SortKey, SortMode, TableSchema};
+use crate::storage::{DatabaseError, FileStorage, SaveOptions, Storage};
 
 /// The possible menu items.
 #[derive(Copy, Clone, PartialEq)]
@@ -27,6 +20,8 @@ pub enum MenuItem {
     Home,
     Menu,
     Clients,
+    Databases,
+    Log,
 }
 
 impl From<MenuItem> for usize {
@@ -35,6 +30,8 @@ impl From<MenuItem> for usize {
             MenuItem::Home => 0,
             MenuItem::Menu => 1,
             MenuItem::Clients => 2,
+            MenuItem::Databases => 3,
+            MenuItem::Log => 4,
         }
     }
 }
@@ -45,7 +42,7 @@ pub enum RecordOption {
     None,
     Add,
     Delete,
-    Edit
+    Edit,
 }
 
 impl From<RecordOption> for usize {
@@ -53,8 +50,86 @@ impl From<RecordOption> for usize {
         match input {
             RecordOption::None => 0,
             RecordOption::Add => 1,
-            RecordOption::Delete => 2,
-            RecordOption::Edit => 3,
+            RecordOption::Edit => 2,
+            RecordOption::Delete => 3,
+        }
+    }
+}
+
+/// Holds the inputs of a record form, one per column of the table.
+#[derive(Default)]
+pub struct RecordForm {
+    pub values: Vec<String>,
+    /// Index of the focused column.
+    pub focus: usize,
+    /// Why each input was rejected the last time the form was submitted.
+    pub errors: Vec<Option<String>>,
+    /// An error that is not about a single input, such as a failed write.
+    pub error: Option<String>,
+}
+
+impl RecordForm {
+    /// Builds an empty form for the columns of `R`.
+    pub fn new<R: Record>() -> RecordForm {
+        RecordForm {
+            values: vec![String::new(); R::SCHEMA.columns.len()],
+            ..RecordForm::default()
+        }
+    }
+
+    /// Builds a form pre-filled with the values of the given record.
+    pub fn from_record<R: Record>(record: &R) -> RecordForm {
+        RecordForm {
+            values: R::SCHEMA.values(record),
+            ..RecordForm::default()
+        }
+    }
+
+    /// Returns the input of the focused column, dropping its error since
+    /// it is about to change.
+    pub fn focused_input_mut(&mut self) -> &mut String {
+        if let Some(error) = self.errors.get_mut(self.focus) {
+            *error = None;
+        }
+        &mut self.values[self.focus]
+    }
+
+    /// Moves the focus to the next column, wrapping around.
+    pub fn next(&mut self) {
+        self.focus = (self.focus + 1) % self.values.len();
+    }
+
+    /// Moves the focus to the previous column, wrapping around.
+    pub fn previous(&mut self) {
+        self.focus = (self.focus + self.values.len() - 1) % self.values.len();
+    }
+
+    /// Empties every input and moves the focus back to the first column.
+    pub fn clear(&mut self) {
+        for value in &mut self.values {
+            value.clear();
+        }
+        self.focus = 0;
+        self.errors.clear();
+        self.error = None;
+    }
+
+    /// Checks the inputs and builds the record they describe. Otherwise
+    /// keeps the error of every rejected input and focuses the first one.
+    pub fn submit<R: Record>(&mut self) -> Option<R> {
+        self.error = None;
+        match R::SCHEMA.validate(&self.values) {
+            Ok(record) => {
+                self.errors.clear();
+                Some(record)
+            }
+            Err(errors) => {
+                if let Some(index) = errors.iter().position(Option::is_some) {
+                    self.focus = index;
+                }
+                self.errors = errors;
+                None
+            }
         }
     }
 }
@@ -65,55 +140,239 @@ pub enum InputMode {
     Editing,
 }
 
-/// The field by which we sort the records.
-#[derive(PartialEq)]
-pub enum SortMode {
-    Id,
-    FirstName,
-    LastName,
-    PhoneNumber,
-    Address,
-}
-
 /// The order in which the sorting is done.
-#[derive(PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum SortOrd {
     Decr,
     Incr,
 }
 
+/// A record of an imported file that could not be turned into a client.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RejectedRow {
+    /// Line of the file the record starts on.
+    pub line: u64,
+    pub reason: String,
+}
+
+/// The outcome of reading a file to import, before anything is added to the database.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ImportReport {
+    pub accepted: Vec<Client>,
+    pub rejected: Vec<RejectedRow>,
+    /// Columns or properties of the file that do not fit any field.
+    pub ignored: Vec<String>,
+}
+
+/// What is done with the path typed into the file prompt.
+#[derive(Clone, Copy, PartialEq)]
+pub enum FileAction {
+    /// Import a CSV file, or a vCard file if it ends in `.vcf`.
+    Import,
+    ExportCsvView,
+    ExportCsvAll,
+    /// Export the record with the given `nr_ordine` as a vCard.
+    ExportVcardSelected(i32),
+    ExportVcardAll,
+}
+
+/// The popup asking for the path of a file to import or export.
+pub struct FilePrompt {
+    pub action: FileAction,
+    pub path: String,
+}
+
+/// A file that was read and waits for the user to confirm its import.
+pub struct ImportPreview {
+    pub path: PathBuf,
+    pub report: ImportReport,
+}
+
 #[derive(Clone, Copy)]
 pub struct Size {
     pub height: u16,
     pub width: u16,
 }
 
-/// Holds the state of the application
+/// Holds the state of the application for one open database
 pub struct App {
+    pub name: String,
     pub query: String,
     pub input_mode: InputMode,
-    pub query_mode: QueryMode,
-    pub sort_mode: SortMode,
-    pub sort_order: SortOrd,
+    /// Index of the column the query is matched against, or `None` for
+    /// every column.
+    pub query_column: Option<usize>,
+    pub search_mode: SearchMode,
+    /// The last query that parsed, whose matches are highlighted.
+    pub search: Query<Client>,
+    /// The mode, text and column the search was last built from.
+    pub searched: Option<(SearchMode, String, Option<usize>)>,
+    /// Why the query does not parse, if it does not.
+    pub query_error: Option<ParseError>,
+    /// The keys the clients are sorted by, the first one deciding first.
+    /// Tab and `+`/`-` change the last one.
+    pub sort_keys: Vec<SortKey>,
     pub data_base: BazaDate,
+    pub add_form: RecordForm,
+    pub edit_form: RecordForm,
+    pub edit_target: Option<i32>,
+    pub delete_target: Option<i32>,
+    /// Whether Up/Down move through the orders of the selected client.
+    pub orders_focused: bool,
+    /// Row selected in the orders pane.
+    pub selected_order: usize,
+    pub order_form: RecordForm,
+    /// The client an order is being added for, while the order form is open.
+    pub order_client: Option<i32>,
+    /// Whether the history of changes is shown next to the clients table.
+    pub show_history: bool,
+    /// The audit log, as read when its tab was last opened.
+    pub audit_entries: Vec<AuditEntry>,
+    /// Text the audit log entries shown must contain.
+    pub audit_filter: String,
+    /// Row selected in the audit log, among the entries shown.
+    pub audit_selected: usize,
+    pub visible_ids: Vec<i32>,
+    pub storage: Box<dyn Storage>,
+    pub save_options: SaveOptions,
+    pub save_status: SaveStatus,
+    pub last_save: Instant,
+    pub prompt: Option<FilePrompt>,
+    pub import_preview: Option<ImportPreview>,
+    /// Outcome of the last import or export, shown in the header.
+    pub message: Option<String>,
     pub window_size: Size,
 }
 
+/// The databases open in the app, each keeping its own `App` state.
+pub struct Workspace {
+    pub apps: Vec<App>,
+    pub active: usize,
+}
+
+impl Workspace {
+    /// The state of the database being worked on.
+    pub fn active_app(&self) -> &App {
+        &self.apps[self.active]
+    }
+}
+
+/// Whether the data in memory has reached the disk.
+#[derive(Clone, PartialEq)]
+pub enum SaveStatus {
+    Saved,
+    Dirty,
+    Failed(String),
+}
+
 impl App {
+    #[allow(dead_code)]
     pub fn size(&self) -> &Size {
         &self.window_size
     }
+
+    /// Undoes the last change, or makes again the last change undone,
+    /// telling in the header what happened.
+    pub fn step_history(&mut self, undo: bool) {
+        let (result, verb, nothing) = match undo {
+            true => (self.data_base.undo(), "Undid", "Nothing to undo"),
+            false => (self.data_base.redo(), "Redid", "Nothing to redo"),
+        };
+        match result {
+            Ok(Some(label)) => {
+                self.mark_dirty();
+                self.message = Some(format!("{}: {}", verb, label));
+            }
+            Ok(None) => self.message = Some(String::from(nothing)),
+            Err(error) => self.save_status = SaveStatus::Failed(error.to_string()),
+        }
+    }
+
+    /// Reads the audit log again, so the latest changes are shown.
+    pub fn load_audit(&mut self) {
+        let log = AuditLog::for_database(self.storage.path());
+        match log.entries() {
+            Ok(entries) => self.audit_entries = entries,
+            Err(error) => self.message = Some(error.to_string()),
+        }
+    }
+
+    /// The audit log entries matching the filter, newest first.
+    pub fn visible_audit(&self) -> Vec<&AuditEntry> {
+        self.audit_entries
+            .iter()
+            .rev()
+            .filter(|entry| entry.matches(&self.audit_filter))
+            .collect()
+    }
+
+    /// Marks the database as changed since the last save.
+    pub fn mark_dirty(&mut self) {
+        self.save_status = SaveStatus::Dirty;
+    }
+
+    /// Writes the database to its storage, remembering whether it worked.
+    pub fn save(&mut self) -> Result<(), DatabaseError> {
+        self.last_save = Instant::now();
+        match self.storage.write(&self.data_base) {
+            Ok(()) => {
+                self.save_status = SaveStatus::Saved;
+                Ok(())
+            }
+            Err(error) => {
+                self.save_status = SaveStatus::Failed(error.to_string());
+                Err(error)
+            }
+        }
+    }
+
+    /// Saves the database if autosave is on, there are unsaved changes
+    /// and the autosave interval has passed since the last save.
+    pub fn autosave(&mut self) {
+        if let Some(interval) = self.save_options.autosave {
+            if self.save_status != SaveStatus::Saved && self.last_save.elapsed() >= interval {
+                let _ = self.save();
+            }
+        }
+    }
 }
 
 impl Default for App {
     fn default() -> App {
         App {
+            name: String::from("baza_de_date"),
             query: String::new(),
             input_mode: InputMode::Normal,
-            query_mode: QueryMode::FirstName,
-            sort_mode: SortMode::Id,
-            sort_order: SortOrd::Incr,
+            query_column: None,
+            search_mode: SearchMode::default(),
+            search: Query::default(),
+            searched: None,
+            query_error: None,
+            sort_keys: vec![SortKey::new(SortMode::Id, SortOrd::Incr)],
             data_base: BazaDate::default(),
+            add_form: RecordForm::new::<Client>(),
+            edit_form: RecordForm::new::<Client>(),
+            edit_target: None,
+            delete_target: None,
+            orders_focused: false,
+            selected_order: 0,
+            order_form: RecordForm::new::<Comanda>(),
+            show_history: false,
+            audit_entries: Vec::new(),
+            audit_filter: String::new(),
+            audit_selected: 0,
+            order_client: None,
+            visible_ids: Vec::new(),
+            storage: Box::new(FileStorage::new(
+                Path::new("./baza_de_date.io"),
+                SaveOptions::default(),
+            )),
+            save_options: SaveOptions::default(),
+            save_status: SaveStatus::Saved,
+            last_save: Instant::now(),
+            prompt: None,
+            import_preview: None,
+            message: None,
             window_size: Size {
                 width: 0,
                 height: 0,
@@ -128,7 +387,8 @@ pub enum Event<I> {
     Tick,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 /// The Client type struct.
 pub struct Client {
     pub nr_ordine: i32,
@@ -138,11 +398,35 @@ pub struct Client {
     pub adresa: String,
 }
 
-#[derive(Debug, Clone, Default)]
+/// An order placed by a client.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Comanda {
+    pub nr_comanda: i32,
+    /// `nr_ordine` of the client who placed the order.
+    pub nr_client: i32,
+    pub produs: String,
+    pub cantitate: i32,
+    pub data: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
 /// The main database type struct.
 pub struct BazaDate {
     pub clienti: Vec<Client>,
+    /// Highest `nr_ordine` given so far. It is never lowered, so the id of
+    /// a deleted record is not given again.
     pub top: i32,
+    pub comenzi: Vec<Comanda>,
+    /// Highest `nr_comanda` given so far.
+    pub top_comenzi: i32,
+    /// Sequence number of the last mutation applied.
+    pub seq: u64,
+    /// Where mutations are recorded before they are applied, if anywhere.
+    pub journal: Option<Journal>,
+    /// Where every mutation is logged for auditing, if anywhere.
+    pub audit: Option<AuditLog>,
+    /// The changes made during the session, which can be undone.
+    pub history: History,
 }
 
 impl Client {
@@ -155,27 +439,427 @@ impl Client {
             adresa: String::new(),
         }
     }
-    pub fn get_row(self) -> Vec<String> {
-        vec![
-            self.nr_ordine.to_string(),
-            self.prenume.clone(),
-            self.nume.clone(),
-            self.nr_telefon.clone(),
-            self.adresa.clone(),
-        ]
+}
+
+/// Names are up to 40 characters long.
+const NAME_LENGTH: Rule = Rule::Length { min: 1, max: 40 };
+
+/// Names start with a letter and hold letters and the punctuation of
+/// compound names.
+const NAME_PATTERN: Rule = Rule::Pattern {
+    pattern: r"\p{L}[\p{L} .'-]*",
+    message: "can only contain letters, spaces and . ' -",
+};
+
+impl Record for Client {
+    const SCHEMA: TableSchema<Client> = TableSchema {
+        name: "clienti",
+        id: "nr_ordine",
+        columns: &[
+            Column {
+                name: "prenume",
+                title: "First Name",
+                aliases: &["first", "first_name", "firstname", "given_name"],
+                kind: ColumnType::Text,
+                required: true,
+                rules: &[NAME_LENGTH, NAME_PATTERN],
+                width: 10,
+                references: None,
+                get: |c| c.prenume.clone(),
+                set: |c, value| c.prenume = value,
+            },
+            Column {
+                name: "nume",
+                title: "Last Name",
+                aliases: &["last", "last_name", "lastname", "surname", "family_name"],
+                kind: ColumnType::Text,
+                required: true,
+                rules: &[NAME_LENGTH, NAME_PATTERN],
+                width: 10,
+                references: None,
+                get: |c| c.nume.clone(),
+                set: |c, value| c.nume = value,
+            },
+            Column {
+                name: "nr_telefon",
+                title: "Phone Number",
+                aliases: &["phone", "phone_number", "telefon", "tel", "mobile"],
+                kind: ColumnType::Phone,
+                required: false,
+                rules: &[],
+                width: 15,
+                references: None,
+                get: |c| c.nr_telefon.clone(),
+                set: |c, value| c.nr_telefon = value,
+            },
+            Column {
+                name: "adresa",
+                title: "Address",
+                aliases: &["address"],
+                kind: ColumnType::Text,
+                required: false,
+                rules: &[Rule::Length { min: 3, max: 100 }],
+                width: 30,
+                references: None,
+                get: |c| c.adresa.clone(),
+                set: |c, value| c.adresa = value,
+            },
+        ],
+    };
+
+    fn id(&self) -> i32 {
+        self.nr_ordine
+    }
+
+    fn set_id(&mut self, id: i32) {
+        self.nr_ordine = id;
+    }
+}
+
+impl Record for Comanda {
+    const SCHEMA: TableSchema<Comanda> = TableSchema {
+        name: "comenzi",
+        id: "nr_comanda",
+        columns: &[
+            Column {
+                name: "nr_client",
+                title: "Client",
+                aliases: &["client"],
+                kind: ColumnType::Integer,
+                required: true,
+                rules: &[],
+                width: 6,
+                references: Some("clienti"),
+                get: |c| c.nr_client.to_string(),
+                set: |c, value| c.nr_client = value.parse().unwrap_or_default(),
+            },
+            Column {
+                name: "produs",
+                title: "Product",
+                aliases: &["product"],
+                kind: ColumnType::Text,
+                required: true,
+                rules: &[Rule::Length { min: 1, max: 50 }],
+                width: 20,
+                references: None,
+                get: |c| c.produs.clone(),
+                set: |c, value| c.produs = value,
+            },
+            Column {
+                name: "cantitate",
+                title: "Quantity",
+                aliases: &["quantity", "qty"],
+                kind: ColumnType::Integer,
+                required: true,
+                rules: &[Rule::Pattern {
+                    pattern: "[1-9][0-9]*",
+                    message: "must be a positive number",
+                }],
+                width: 8,
+                references: None,
+                get: |c| c.cantitate.to_string(),
+                set: |c, value| c.cantitate = value.parse().unwrap_or_default(),
+            },
+            Column {
+                name: "data",
+                title: "Date",
+                aliases: &["date"],
+                kind: ColumnType::Text,
+                required: false,
+                rules: &[Rule::Pattern {
+                    pattern: r"\d{4}-\d{2}-\d{2}",
+                    message: "must be written like 2026-10-01",
+                }],
+                width: 10,
+                references: None,
+                get: |c| c.data.clone(),
+                set: |c, value| c.data = value,
+            },
+        ],
+    };
+
+    fn id(&self) -> i32 {
+        self.nr_comanda
+    }
+
+    fn set_id(&mut self, id: i32) {
+        self.nr_comanda = id;
     }
 }
 
+/// What happens to the orders of a client being deleted.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OnDelete {
+    /// Refuse to delete a client who still has orders.
+    Restrict,
+    /// Delete the orders along with the client.
+    Cascade,
+}
+
+/// Mutations refused because orders would point to a missing client.
+#[derive(Debug, Error)]
+pub enum IntegrityError {
+    #[error("there is no client {0}")]
+    MissingClient(i32),
+    #[error("client {id} still has {orders} orders")]
+    HasOrders { id: i32, orders: usize },
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
 impl BazaDate {
     pub fn new() -> Self {
         BazaDate {
             clienti: Vec::new(),
             top: -1,
+            comenzi: Vec::new(),
+            top_comenzi: -1,
+            seq: 0,
+            journal: None,
+            audit: None,
+            history: History::default(),
+        }
+    }
+
+    /// Appends a record read from a file, keeping its `nr_ordine`.
+    /// Nothing is journaled, since the record is already on disk.
+    pub fn push_record(&mut self, client: Client) {
+        self.top = self.top.max(client.nr_ordine);
+        self.clienti.push(client);
+    }
+
+    /// Adds a record to the database, giving it the next `nr_ordine`.
+    pub fn add_record(&mut self, mut client: Client) -> io::Result<i32> {
+        client.nr_ordine = self.top + 1;
+        let nr_ordine = client.nr_ordine;
+        self.record(Mutation::Add(client))?;
+        Ok(nr_ordine)
+    }
+
+    /// Adds a record keeping its `nr_ordine`, as when restoring an export.
+    /// Returns `false` if the `nr_ordine` is already used.
+    pub fn insert_record(&mut self, client: Client) -> io::Result<bool> {
+        if self.get_record(client.nr_ordine).is_some() {
+            return Ok(false);
+        }
+        self.record(Mutation::Add(client))?;
+        Ok(true)
+    }
+
+    /// Replaces the fields of the record with the given `nr_ordine`.
+    /// Returns `false` if there is no such record.
+    pub fn update_record(&mut self, nr_ordine: i32, mut client: Client) -> io::Result<bool> {
+        if self.get_record(nr_ordine).is_none() {
+            return Ok(false);
+        }
+        client.nr_ordine = nr_ordine;
+        self.record(Mutation::Update(client))?;
+        Ok(true)
+    }
+
+    /// Returns the record with the given `nr_ordine`.
+    pub fn get_record(&self, nr_ordine: i32) -> Option<&Client> {
+        self.clienti.iter().find(|c| c.nr_ordine == nr_ordine)
+    }
+
+    /// Deletes the record with the given `nr_ordine` from the database,
+    /// along with its orders if `on_delete` allows it.
+    pub fn delete_record(
+        &mut self,
+        nr_ordine: i32,
+        on_delete: OnDelete,
+    ) -> Result<Option<Client>, IntegrityError> {
+        if self.get_record(nr_ordine).is_none() {
+            return Ok(None);
+        }
+        let orders: Vec<i32> = self
+            .orders_of(nr_ordine)
+            .map(|order| order.nr_comanda)
+            .collect();
+        if !orders.is_empty() && on_delete == OnDelete::Restrict {
+            return Err(IntegrityError::HasOrders {
+                id: nr_ordine,
+                orders: orders.len(),
+            });
+        }
+        if orders.is_empty() {
+            return Ok(self.record(Mutation::Delete(nr_ordine))?);
+        }
+        let label = format!("Delete client {} with {} orders", nr_ordine, orders.len());
+        self.group(label, |bd| {
+            for nr_comanda in orders {
+                bd.record(Mutation::DeleteOrder(nr_comanda))?;
+            }
+            Ok(bd.record(Mutation::Delete(nr_ordine))?)
+        })
+    }
+
+    /// The orders placed by the client with the given `nr_ordine`.
+    pub fn orders_of(&self, nr_ordine: i32) -> impl Iterator<Item = &Comanda> {
+        self.comenzi
+            .iter()
+            .filter(move |order| order.nr_client == nr_ordine)
+    }
+
+    /// Appends an order read from a file, keeping its `nr_comanda`.
+    /// Nothing is journaled, since the order is already on disk.
+    pub fn push_order(&mut self, order: Comanda) {
+        self.top_comenzi = self.top_comenzi.max(order.nr_comanda);
+        self.comenzi.push(order);
+    }
+
+    /// Adds an order for an existing client, giving it the next `nr_comanda`.
+    pub fn add_order(&mut self, mut order: Comanda) -> Result<i32, IntegrityError> {
+        if self.get_record(order.nr_client).is_none() {
+            return Err(IntegrityError::MissingClient(order.nr_client));
         }
+        order.nr_comanda = self.top_comenzi + 1;
+        let nr_comanda = order.nr_comanda;
+        self.record(Mutation::AddOrder(order))?;
+        Ok(nr_comanda)
+    }
+
+    /// Returns the order with the given `nr_comanda`.
+    pub fn get_order(&self, nr_comanda: i32) -> Option<&Comanda> {
+        self.comenzi.iter().find(|c| c.nr_comanda == nr_comanda)
+    }
+
+    /// Deletes the order with the given `nr_comanda`.
+    pub fn delete_order(&mut self, nr_comanda: i32) -> io::Result<Option<Comanda>> {
+        let order = match self.get_order(nr_comanda) {
+            Some(order) => order.clone(),
+            None => return Ok(None),
+        };
+        self.record(Mutation::DeleteOrder(nr_comanda))?;
+        Ok(Some(order))
     }
 
-    /// Deletes record from database.
-    pub fn delete_record(&mut self, index: usize) {
-        self.clienti.remove(index);
+    /// Makes every mutation done by `f` part of a single change of the
+    /// history, so they are undone together.
+    pub fn group<T>(&mut self, label: String, f: impl FnOnce(&mut BazaDate) -> T) -> T {
+        let opened = self.history.begin(label);
+        let result = f(self);
+        if opened {
+            self.history.end();
+        }
+        result
+    }
+
+    /// Undoes the last change still in the history, returning its label.
+    ///
+    /// If a mutation can't be written, the part of the change already
+    /// undone moves to the redo list and the rest stays to be undone.
+    pub fn undo(&mut self) -> io::Result<Option<String>> {
+        let change = match self.history.undo.pop() {
+            Some(change) => change,
+            None => return Ok(None),
+        };
+        let label = change.label.clone();
+        let (done, result) = self.commit_all(&change.inverse);
+        let at = change.forward.len() - done;
+        let (rest, undone) = change.split(at);
+        self.history.keep(rest, undone);
+        result.map(|()| Some(label))
+    }
+
+    /// Makes again the last change undone, returning its label.
+    ///
+    /// If a mutation can't be written, the part of the change already
+    /// redone moves to the undo list and the rest stays to be redone.
+    pub fn redo(&mut self) -> io::Result<Option<String>> {
+        let change = match self.history.redo.pop() {
+            Some(change) => change,
+            None => return Ok(None),
+        };
+        let label = change.label.clone();
+        let (done, result) = self.commit_all(&change.forward);
+        let (redone, rest) = change.split(done);
+        self.history.keep(redone, rest);
+        result.map(|()| Some(label))
+    }
+
+    /// Commits mutations in order until one fails, returning how many were
+    /// committed.
+    fn commit_all(&mut self, mutations: &[Mutation]) -> (usize, io::Result<()>) {
+        for (done, mutation) in mutations.iter().enumerate() {
+            if let Err(error) = self.commit(mutation.clone()) {
+                return (done, Err(error));
+            }
+        }
+        (mutations.len(), Ok(()))
+    }
+
+    /// Makes a mutation and keeps it in the history.
+    fn record(&mut self, mutation: Mutation) -> io::Result<Option<Client>> {
+        let inverse = self.inverse(&mutation);
+        let replaced = self.commit(mutation.clone())?;
+        if let Some(inverse) = inverse {
+            self.history.push(mutation, inverse);
+        }
+        Ok(replaced)
+    }
+
+    /// Writes the mutation to the journal and the audit log, then applies it.
+    ///
+    /// The journal comes first, so the audit log never holds a mutation
+    /// that was not made. If the audit log then fails the mutation is not
+    /// applied, but as the journal holds it, it is on the next load, with
+    /// no audit entry.
+    fn commit(&mut self, mutation: Mutation) -> io::Result<Option<Client>> {
+        if let Some(journal) = &self.journal {
+            journal.append(self.seq + 1, &mutation)?;
+        }
+        if let Some(audit) = &self.audit {
+            audit.append(&AuditEntry::new(self, &mutation))?;
+        }
+        self.seq += 1;
+        Ok(self.apply(mutation))
+    }
+
+    /// The mutation undoing `mutation`, if it would change anything.
+    fn inverse(&self, mutation: &Mutation) -> Option<Mutation> {
+        match mutation {
+            Mutation::Add(client) => Some(Mutation::Delete(client.nr_ordine)),
+            Mutation::Update(client) => self
+                .get_record(client.nr_ordine)
+                .cloned()
+                .map(Mutation::Update),
+            Mutation::Delete(nr_ordine) => self.get_record(*nr_ordine).cloned().map(Mutation::Add),
+            Mutation::AddOrder(order) => Some(Mutation::DeleteOrder(order.nr_comanda)),
+            Mutation::DeleteOrder(nr_comanda) => {
+                self.get_order(*nr_comanda).cloned().map(Mutation::AddOrder)
+            }
+        }
+    }
+
+    /// Applies a mutation in memory, returning the client it replaced or removed.
+    pub fn apply(&mut self, mutation: Mutation) -> Option<Client> {
+        match mutation {
+            Mutation::Add(client) => {
+                self.top = self.top.max(client.nr_ordine);
+                self.clienti.push(client);
+                None
+            }
+            Mutation::Update(client) => {
+                let record = self
+                    .clienti
+                    .iter_mut()
+                    .find(|c| c.nr_ordine == client.nr_ordine)?;
+                Some(std::mem::replace(record, client))
+            }
+            Mutation::Delete(nr_ordine) => {
+                let index = self.clienti.iter().position(|c| c.nr_ordine == nr_ordine)?;
+                Some(self.clienti.remove(index))
+            }
+            Mutation::AddOrder(order) => {
+                self.top_comenzi = self.top_comenzi.max(order.nr_comanda);
+                self.comenzi.push(order);
+                None
+            }
+            Mutation::DeleteOrder(nr_comanda) => {
+                self.comenzi.retain(|c| c.nr_comanda != nr_comanda);
+                None
+            }
+        }
     }
 }
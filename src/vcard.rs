@@ -0,0 +1,491 @@
+use std::{
+    borrow::Borrow,
+    collections::BTreeMap,
+    io::{self, Write},
+};
+
+use crate::schema::Record;
+use crate::types::{Client, ImportReport, RejectedRow};
+
+/// Longest line written, in octets, before it is folded.
+const MAX_LINE: usize = 75;
+
+/// The vCard versions that can be written.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VcardVersion {
+    V3,
+    V4,
+}
+
+impl VcardVersion {
+    fn as_str(self) -> &'static str {
+        match self {
+            VcardVersion::V3 => "3.0",
+            VcardVersion::V4 => "4.0",
+        }
+    }
+}
+
+/// A content line of a card, after unfolding.
+struct Property {
+    /// Line of the file the property starts on.
+    line: u64,
+    name: String,
+    params: Vec<(String, String)>,
+    value: String,
+}
+
+impl Property {
+    /// Whether the property has the parameter, like `TYPE=pref` or a bare `PREF`.
+    fn has_param(&self, key: &str, value: &str) -> bool {
+        self.params.iter().any(|(k, v)| {
+            k == key
+                && v.split(',')
+                    .any(|item| item.trim_matches('"').eq_ignore_ascii_case(value))
+        })
+    }
+
+    fn is_preferred(&self) -> bool {
+        self.has_param("TYPE", "pref") || self.params.iter().any(|(key, _)| key == "PREF")
+    }
+}
+
+/// Reads the cards of a `.vcf` file.
+///
+/// `N`, `FN`, `TEL` and `ADR` are mapped onto the client fields; every other
+/// property, and every `TEL` or `ADR` after the one that was used, is
+/// counted in `ignored`. Cards that fail the record form checks are rejected.
+pub fn read_vcards(input: &str) -> ImportReport {
+    let mut report = ImportReport::default();
+    let mut ignored: BTreeMap<String, usize> = BTreeMap::new();
+    let mut card: Option<(u64, Vec<Property>)> = None;
+
+    for property in unfold(input) {
+        let property = match property {
+            Ok(property) => property,
+            Err((line, reason)) => {
+                report.rejected.push(RejectedRow { line, reason });
+                continue;
+            }
+        };
+
+        match (property.name.as_str(), &mut card) {
+            ("BEGIN", None) if property.value.eq_ignore_ascii_case("VCARD") => {
+                card = Some((property.line, Vec::new()));
+            }
+            ("END", Some(_)) if property.value.eq_ignore_ascii_case("VCARD") => {
+                let (line, properties) = card.take().unwrap_or_default();
+                match card_to_client(&properties, &mut ignored) {
+                    Ok(client) => report.accepted.push(client),
+                    Err(reason) => report.rejected.push(RejectedRow { line, reason }),
+                }
+            }
+            ("BEGIN", Some((line, _))) => {
+                report.rejected.push(RejectedRow {
+                    line: *line,
+                    reason: String::from("card is not closed by END:VCARD"),
+                });
+                card = Some((property.line, Vec::new()));
+            }
+            (_, Some((_, properties))) => properties.push(property),
+            (_, None) => report.rejected.push(RejectedRow {
+                line: property.line,
+                reason: format!("`{}` outside of a card", property.name),
+            }),
+        }
+    }
+    if let Some((line, _)) = card {
+        report.rejected.push(RejectedRow {
+            line,
+            reason: String::from("card is not closed by END:VCARD"),
+        });
+    }
+
+    report.ignored = ignored
+        .into_iter()
+        .map(|(name, cards)| match cards {
+            1 => format!("{} (1 card)", name),
+            cards => format!("{} ({} cards)", name, cards),
+        })
+        .collect();
+    report
+}
+
+/// Maps the properties of one card onto a client.
+fn card_to_client(
+    properties: &[Property],
+    ignored: &mut BTreeMap<String, usize>,
+) -> Result<Client, String> {
+    let mut unused: Vec<String> = Vec::new();
+    let mut form = Client::new();
+
+    if let Some(version) = properties.iter().find(|p| p.name == "VERSION") {
+        if !["2.1", "3.0", "4.0"].contains(&version.value.as_str()) {
+            return Err(format!("unsupported vCard version {}", version.value));
+        }
+    }
+
+    let name = properties.iter().find(|p| p.name == "N");
+    match name {
+        Some(name) => {
+            let components = split_structured(&name.value);
+            form.nume = components.first().cloned().unwrap_or_default();
+            form.prenume = components
+                .iter()
+                .skip(1)
+                .take(2)
+                .filter(|component| !component.is_empty())
+                .cloned()
+                .collect::<Vec<_>>()
+                .join(" ");
+        }
+        None => {
+            if let Some(full_name) = properties.iter().find(|p| p.name == "FN") {
+                let full_name = unescape(&full_name.value);
+                match full_name.trim().rsplit_once(' ') {
+                    Some((prenume, nume)) => {
+                        form.prenume = prenume.to_string();
+                        form.nume = nume.to_string();
+                    }
+                    None => form.nume = full_name,
+                }
+            }
+        }
+    }
+
+    let phones: Vec<&Property> = properties.iter().filter(|p| p.name == "TEL").collect();
+    if let Some(phone) = phones
+        .iter()
+        .find(|p| p.is_preferred())
+        .or_else(|| phones.first())
+    {
+        let number = unescape(&phone.value);
+        let number = number.strip_prefix("tel:").unwrap_or(&number);
+        form.nr_telefon = number
+            .chars()
+            .filter(|c| !matches!(c, '-' | '.' | '(' | ')'))
+            .collect();
+        if phones.len() > 1 {
+            unused.push(String::from("additional TEL"));
+        }
+    }
+
+    let addresses: Vec<&Property> = properties.iter().filter(|p| p.name == "ADR").collect();
+    if let Some(address) = addresses
+        .iter()
+        .find(|p| p.is_preferred())
+        .or_else(|| addresses.first())
+    {
+        form.adresa = split_structured(&address.value)
+            .into_iter()
+            .filter(|component| !component.is_empty())
+            .collect::<Vec<_>>()
+            .join(", ");
+        if addresses.len() > 1 {
+            unused.push(String::from("additional ADR"));
+        }
+    }
+
+    for property in properties {
+        let mapped = matches!(
+            property.name.as_str(),
+            "VERSION" | "N" | "FN" | "TEL" | "ADR"
+        );
+        if !mapped && !unused.contains(&property.name) {
+            unused.push(property.name.clone());
+        }
+    }
+
+    let client = Client::SCHEMA.check(&form)?;
+    for name in unused {
+        *ignored.entry(name).or_default() += 1;
+    }
+    Ok(client)
+}
+
+/// Joins folded lines and splits them into properties.
+///
+/// Lines starting with a space or a tab continue the previous one, and
+/// so do the lines after a quoted-printable value ending in `=`.
+fn unfold(input: &str) -> Vec<Result<Property, (u64, String)>> {
+    let mut logical: Vec<(u64, String)> = Vec::new();
+    for (index, line) in input.split('\n').enumerate() {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        let continues_soft_break = logical.last().is_some_and(|(_, previous)| {
+            previous.ends_with('=') && previous.to_uppercase().contains("QUOTED-PRINTABLE")
+        });
+        match logical.last_mut() {
+            Some((_, previous)) if line.starts_with([' ', '\t']) => previous.push_str(&line[1..]),
+            Some((_, previous)) if continues_soft_break => {
+                previous.pop();
+                previous.push_str(line);
+            }
+            _ if line.trim().is_empty() => {}
+            _ => logical.push((index as u64 + 1, line.to_string())),
+        }
+    }
+
+    logical
+        .into_iter()
+        .map(|(line, text)| {
+            parse_property(line, &text).ok_or((line, format!("malformed line `{}`", text)))
+        })
+        .collect()
+}
+
+/// Parses `[group.]NAME[;PARAM=VALUE...]:VALUE`.
+fn parse_property(line: u64, text: &str) -> Option<Property> {
+    let mut quoted = false;
+    let colon = text.char_indices().find_map(|(index, c)| match c {
+        '"' => {
+            quoted = !quoted;
+            None
+        }
+        ':' if !quoted => Some(index),
+        _ => None,
+    })?;
+    let (head, value) = (&text[..colon], &text[colon + 1..]);
+
+    let mut parts = head.split(';');
+    let name = parts.next()?;
+    let name = name.rsplit('.').next()?.trim().to_uppercase();
+    if name.is_empty() {
+        return None;
+    }
+    let params: Vec<(String, String)> = parts
+        .map(|param| match param.split_once('=') {
+            Some((key, value)) => (key.trim().to_uppercase(), value.to_string()),
+            None => (String::from("TYPE"), param.to_string()),
+        })
+        .collect();
+
+    let quoted_printable = params
+        .iter()
+        .any(|(key, value)| key == "ENCODING" && value.eq_ignore_ascii_case("QUOTED-PRINTABLE"));
+    let value = if quoted_printable {
+        decode_quoted_printable(value)
+    } else {
+        value.to_string()
+    };
+
+    Some(Property {
+        line,
+        name,
+        params,
+        value,
+    })
+}
+
+/// Decodes the `=XX` escapes of vCard 2.1 quoted-printable values as UTF-8.
+fn decode_quoted_printable(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let escaped = match (bytes[index], bytes.get(index + 1..index + 3)) {
+            (b'=', Some(hex)) => std::str::from_utf8(hex)
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+            _ => None,
+        };
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            None => {
+                decoded.push(bytes[index]);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Splits a structured value such as `N` or `ADR` on its unescaped `;`.
+fn split_structured(value: &str) -> Vec<String> {
+    let mut components = vec![String::new()];
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                let last = components.last_mut().unwrap();
+                last.push('\\');
+                if let Some(next) = chars.next() {
+                    last.push(next);
+                }
+            }
+            ';' => components.push(String::new()),
+            c => components.last_mut().unwrap().push(c),
+        }
+    }
+    components
+        .iter()
+        .map(|component| unescape(component).trim().to_string())
+        .collect()
+}
+
+/// Reverses `escape`.
+fn unescape(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') | Some('N') => unescaped.push('\n'),
+            Some(other) => unescaped.push(other),
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+/// Escapes a text value.
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            ',' => escaped.push_str("\\,"),
+            ';' => escaped.push_str("\\;"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Writes a content line, folding it so no line is longer than `MAX_LINE` octets.
+fn write_line(writer: &mut impl Write, line: &str) -> io::Result<()> {
+    let mut rest = line;
+    let mut limit = MAX_LINE;
+    while rest.len() > limit {
+        let mut split = limit;
+        while !rest.is_char_boundary(split) {
+            split -= 1;
+        }
+        write!(writer, "{}\r\n ", &rest[..split])?;
+        rest = &rest[split..];
+        limit = MAX_LINE - 1;
+    }
+    write!(writer, "{}\r\n", rest)
+}
+
+/// Writes every client as a card of the given version.
+pub fn write_vcards(
+    mut writer: impl Write,
+    clients: impl IntoIterator<Item = impl Borrow<Client>>,
+    version: VcardVersion,
+) -> io::Result<()> {
+    for client in clients {
+        let client = client.borrow();
+        write_line(&mut writer, "BEGIN:VCARD")?;
+        write_line(&mut writer, &format!("VERSION:{}", version.as_str()))?;
+        write_line(
+            &mut writer,
+            &format!("N:{};{};;;", escape(&client.nume), escape(&client.prenume)),
+        )?;
+        write_line(
+            &mut writer,
+            &format!(
+                "FN:{}",
+                escape(&format!("{} {}", client.prenume, client.nume))
+            ),
+        )?;
+        if !client.nr_telefon.is_empty() {
+            let line = match version {
+                VcardVersion::V3 => format!("TEL;TYPE=CELL:{}", escape(&client.nr_telefon)),
+                VcardVersion::V4 => format!(
+                    "TEL;VALUE=uri;TYPE=cell:tel:{}",
+                    client.nr_telefon.replace(' ', "-")
+                ),
+            };
+            write_line(&mut writer, &line)?;
+        }
+        if !client.adresa.is_empty() {
+            write_line(
+                &mut writer,
+                &format!("ADR;TYPE=home:;;{};;;;", escape(&client.adresa)),
+            )?;
+        }
+        write_line(&mut writer, "END:VCARD")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_cards_from_phones_and_reports_extra_properties() {
+        let input = "BEGIN:VCARD\r\n\
+                     VERSION:2.1\r\n\
+                     N;CHARSET=UTF-8;ENCODING=QUOTED-PRINTABLE:Stanciu;=C8=98tefan;;;\r\n\
+                     TEL;CELL:0722-123-456\r\n\
+                     TEL;HOME:021 000\r\n\
+                     EMAIL:stefan@example.com\r\n\
+                     END:VCARD\r\n\
+                     BEGIN:VCARD\r\n\
+                     VERSION:4.0\r\n\
+                     FN:Ana Maria Pop\r\n\
+                     TEL;VALUE=uri;TYPE=\"cell,voice\":tel:+40-700-123-456\r\n\
+                     ADR;TYPE=work:;;Str. Lunga 1\\, ap. 2;Cluj;\r\n \
+                     ;400000;Romania\r\n\
+                     EMAIL:ana@example.com\r\n\
+                     END:VCARD\r\n\
+                     BEGIN:VCARD\r\n\
+                     VERSION:3.0\r\n\
+                     TEL:0733\r\n\
+                     END:VCARD\r\n";
+        let report = read_vcards(input);
+
+        assert_eq!(report.accepted.len(), 2);
+        assert_eq!(report.accepted[0].prenume, "\u{218}tefan");
+        assert_eq!(report.accepted[0].nr_telefon, "0722123456");
+        assert_eq!(report.accepted[1].prenume, "Ana Maria");
+        assert_eq!(report.accepted[1].nume, "Pop");
+        assert_eq!(report.accepted[1].nr_telefon, "+40700123456");
+        assert_eq!(
+            report.accepted[1].adresa,
+            "Str. Lunga 1, ap. 2, Cluj, 400000, Romania"
+        );
+        assert_eq!(
+            report.ignored,
+            vec![
+                String::from("EMAIL (2 cards)"),
+                String::from("additional TEL (1 card)")
+            ]
+        );
+        assert_eq!(report.rejected.len(), 1);
+        assert_eq!(report.rejected[0].line, 16);
+    }
+
+    #[test]
+    fn export_reads_back() {
+        let clients = vec![Client {
+            nr_ordine: 0,
+            nume: String::from("Popescu-Ionescu"),
+            prenume: String::from("Ion"),
+            nr_telefon: String::from("+40722000111"),
+            adresa:
+                "Bulevardul Eroilor Sanitari, nr. 123; bloc A, scara 4, etaj 7, ap. 89, Bucuresti"
+                    .to_string(),
+        }];
+        for version in [VcardVersion::V3, VcardVersion::V4] {
+            let mut output = Vec::new();
+            write_vcards(&mut output, &clients, version).unwrap();
+            let output = String::from_utf8(output).unwrap();
+            assert!(output.lines().all(|line| line.len() <= MAX_LINE));
+
+            let report = read_vcards(&output);
+            assert!(report.ignored.is_empty());
+            assert_eq!(report.accepted[0].nume, clients[0].nume);
+            assert_eq!(report.accepted[0].adresa, clients[0].adresa);
+        }
+    }
+}